@@ -0,0 +1,75 @@
+use std::fmt::Debug;
+
+/// Loads `$ty` from the given args, env vars and config file contents (read
+/// for whatever config path the struct resolves), and asserts the result
+/// equals the expected value. On a mismatch it panics with a line diff of the
+/// two `{:#?}` renderings.
+///
+/// ```ignore
+/// assert_config_matches!(
+///     Opts,
+///     args: ["app", "--port", "8080"],
+///     env: [("HOST", "env.example.com")],
+///     file: "host: file.example.com\nport: 80\n",
+///     Opts { config: "config.yml".into(), host: Some("env.example.com".into()), port: 8080 },
+/// );
+/// ```
+#[macro_export]
+macro_rules! assert_config_matches {
+    (
+        $ty:ty,
+        args: [$($arg:expr),* $(,)?],
+        env: [$(($key:expr, $value:expr)),* $(,)?],
+        file: $file:expr,
+        $expected:expr $(,)?
+    ) => {{
+        let args: ::std::vec::Vec<::std::string::String> = ::std::vec![$(::std::string::ToString::to_string(&$arg)),*];
+        let env: ::std::collections::HashMap<::std::string::String, ::std::string::String> =
+            ::std::collections::HashMap::from([$((::std::string::ToString::to_string(&$key), ::std::string::ToString::to_string(&$value))),*]);
+        let file: &str = $file;
+        let actual = <$ty as $crate::ConfigLoader>::load_config_from(&args, &env, |_| ::std::result::Result::Ok(file.to_string()))
+            .unwrap_or_else(|err| ::std::panic!("failed to load {}: {err}", ::std::stringify!($ty)));
+        let expected: $ty = $expected;
+        if actual != expected {
+            ::std::panic!("loaded config does not match (- expected, + actual):\n{}", $crate::debug_diff(&expected, &actual));
+        }
+    }};
+}
+
+/// The lines of `expected` and `actual`'s `{:#?}` renderings, kept lines
+/// prefixed with two spaces and changed ones with `- ` and `+ `.
+#[doc(hidden)]
+pub fn debug_diff(expected: &impl Debug, actual: &impl Debug) -> String {
+    let expected = format!("{expected:#?}");
+    let actual = format!("{actual:#?}");
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+    // common[i][j] is the length of the longest common subsequence of
+    // expected[i..] and actual[j..].
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff.push_str(&format!("  {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        }
+    }
+    diff
+}
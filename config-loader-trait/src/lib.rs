@@ -0,0 +1,322 @@
+//! The types shared by every `#[derive(LoadConfig)]` struct: the
+//! [`ConfigLoader`] trait the derive implements, the [`ConfigError`] it
+//! returns, and the context, source and description types its methods take
+//! and hand out. The derive refers to them by path, so a crate deriving
+//! `LoadConfig` depends on this one too. [`assert_config_matches!`] checks
+//! what such a struct loads from a given set of inputs.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+mod assert;
+#[cfg(feature = "ssm")]
+mod ssm;
+
+#[doc(hidden)]
+pub use assert::debug_diff;
+
+#[cfg(feature = "ssm")]
+pub use ssm::SsmParameterStore;
+
+/// Loads a struct from the CLI, env, config file and parameter sources, in
+/// that order of precedence, and writes it back out.
+pub trait ConfigLoader: Sized {
+    fn load_config() -> Result<Self, ConfigError>;
+    fn load_config_with_prefix(prefix: &str) -> Result<Self, ConfigError>;
+    fn load_config_with_source(source: &dyn ConfigSource) -> Result<Self, ConfigError>;
+    fn load_config_with_context(ctx: &LoadContext) -> Result<Self, ConfigError>;
+    fn load_config_with_sources() -> Result<(Self, HashMap<String, Source>), ConfigError>;
+    fn load_config_from_args(args: &[String]) -> Result<Self, ConfigError>;
+    fn load_config_from(
+        args: &[String],
+        env: &HashMap<String, String>,
+        read: impl Fn(&str) -> std::io::Result<String>,
+    ) -> Result<Self, ConfigError>;
+    fn default_values() -> Result<Self, ConfigError>;
+    fn config_values(path: impl AsRef<Path>) -> Result<Self, ConfigError>;
+    fn list_profiles(path: &Path) -> Result<Vec<String>, ConfigError>;
+    fn describe() -> ConfigDescription;
+    fn dump_minimal_config(&self) -> Result<String, ConfigError>;
+    fn raw_config_value() -> Result<Option<serde_yaml::Value>, ConfigError>;
+    fn update_from_args(&mut self, args: &[String]) -> Result<(), ConfigError>;
+    fn config_hash(&self) -> u64;
+    fn to_canonical_yaml(&self) -> Result<String, ConfigError>;
+    fn dump_config(&self) -> Result<String, ConfigError>;
+    fn dump_config_as(&self, format: ConfigFormat) -> Result<String, ConfigError>;
+    fn init_config(path: &Path, overwrite: bool) -> Result<(), ConfigError>;
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    /// The config file at `path` (empty for in-memory YAML) is not valid in
+    /// its format or does not match the struct.
+    Parse {
+        path: String,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    MissingRequired(&'static str),
+    /// The config file at `path` is bigger than max_file_size.
+    TooLarge {
+        path: String,
+        limit: u64,
+    },
+    /// The config file at `path` does not match its `.sha256` digest, or the
+    /// digest cannot be read.
+    IntegrityCheck {
+        path: String,
+        message: String,
+    },
+    /// A mapping in the config file at `path` (empty for in-memory YAML)
+    /// repeats `key`.
+    DuplicateKey {
+        key: String,
+        path: String,
+    },
+    /// The `${field}` references that lead back to where they started.
+    CyclicReference(Vec<&'static str>),
+    /// The flattened `field` failed to load.
+    Nested {
+        field: &'static str,
+        source: Box<ConfigError>,
+    },
+    /// The config was read but rejected: template errors, undefined variables
+    /// or malformed .env lines.
+    Invalid(String),
+    /// The loaded config was rejected by the validate hook.
+    Validation(String),
+    Args(clap::Error),
+    Source(String),
+    Serialize(Box<dyn Error + Send + Sync>),
+}
+
+impl ConfigError {
+    pub fn serialize(source: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self::Serialize(source.into())
+    }
+
+    pub fn parse(path: &Path, source: impl Into<Box<dyn Error + Send + Sync>>) -> Self {
+        Self::Parse {
+            path: path.display().to_string(),
+            source: source.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Parse { path, source } if path.is_empty() => write!(f, "failed to parse config: {source}"),
+            Self::Parse { path, source } => write!(f, "failed to parse config file {path}: {source}"),
+            Self::MissingRequired(name) => write!(f, "missing required config value `{name}`"),
+            Self::TooLarge { path, limit } => write!(f, "config file {path} exceeds max_file_size of {limit} bytes"),
+            Self::IntegrityCheck { path, message } => write!(f, "integrity check failed for {path}: {message}"),
+            Self::DuplicateKey { key, path } if path.is_empty() => write!(f, "duplicate key `{key}`"),
+            Self::DuplicateKey { key, path } => write!(f, "duplicate key `{key}` in config file {path}"),
+            Self::CyclicReference(cycle) => write!(f, "cyclic field reference: {}", cycle.join(" -> ")),
+            Self::Nested { field, source } => write!(f, "{field}: {source}"),
+            Self::Invalid(message) => write!(f, "{message}"),
+            Self::Validation(message) => write!(f, "invalid config: {message}"),
+            Self::Args(err) => write!(f, "{err}"),
+            Self::Source(message) => write!(f, "config source error: {message}"),
+            Self::Serialize(err) => write!(f, "failed to serialize config: {err}"),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Parse { source, .. } => Some(source.as_ref()),
+            Self::Nested { source, .. } => Some(source.as_ref()),
+            Self::Args(err) => Some(err),
+            Self::Serialize(err) => Some(err.as_ref()),
+            Self::MissingRequired(_)
+            | Self::TooLarge { .. }
+            | Self::IntegrityCheck { .. }
+            | Self::DuplicateKey { .. }
+            | Self::CyclicReference(_)
+            | Self::Invalid(_)
+            | Self::Validation(_)
+            | Self::Source(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<clap::Error> for ConfigError {
+    fn from(err: clap::Error) -> Self {
+        Self::Args(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// The format follows the extension; anything else is YAML.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::Toml,
+            Some("json") => Self::Json,
+            _ => Self::Yaml,
+        }
+    }
+}
+
+/// Reads a config file's contents in place of the filesystem.
+pub type ReadFile<'a> = Rc<dyn Fn(&Path) -> std::io::Result<String> + 'a>;
+
+/// Lists the file names in a directory in place of the filesystem.
+pub type ReadDir<'a> = Rc<dyn Fn(&Path) -> std::io::Result<Vec<String>> + 'a>;
+
+/// Everything the loader reads from the outside world. load_config uses
+/// [`LoadContext::from_process`]; tests can build a synthetic one instead,
+/// whose file reader may borrow from the test.
+#[derive(Clone)]
+pub struct LoadContext<'a> {
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub cwd: PathBuf,
+    pub now: SystemTime,
+    pub read_file: Option<ReadFile<'a>>,
+    pub read_dir: Option<ReadDir<'a>>,
+}
+
+impl LoadContext<'_> {
+    pub fn from_process() -> Self {
+        Self {
+            args: std::env::args().collect(),
+            env: std::env::vars_os()
+                .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+                .collect(),
+            cwd: std::env::current_dir().unwrap_or_default(),
+            now: SystemTime::now(),
+            read_file: None,
+            read_dir: None,
+        }
+    }
+
+    pub fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        match &self.read_file {
+            Some(read_file) => read_file(path),
+            None => std::fs::read_to_string(path),
+        }
+    }
+
+    /// Names that are not valid UTF-8 are skipped.
+    pub fn dir_entries(&self, path: &Path) -> std::io::Result<Vec<String>> {
+        match &self.read_dir {
+            Some(read_dir) => read_dir(path),
+            None => Ok(std::fs::read_dir(path)?
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .collect()),
+        }
+    }
+
+    pub fn file_exists(&self, path: &Path) -> bool {
+        match &self.read_file {
+            Some(read_file) => read_file(path).is_ok(),
+            None => path.exists(),
+        }
+    }
+}
+
+impl std::fmt::Debug for LoadContext<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoadContext")
+            .field("args", &self.args)
+            .field("env", &self.env)
+            .field("cwd", &self.cwd)
+            .field("now", &self.now)
+            .field("read_file", &self.read_file.as_ref().map(|_| "<fn>"))
+            .field("read_dir", &self.read_dir.as_ref().map(|_| "<fn>"))
+            .finish()
+    }
+}
+
+/// What [`ConfigLoader::describe`] reports about a struct: one entry per
+/// loaded field, flattened fields included.
+#[derive(Clone, Debug)]
+pub struct ConfigDescription {
+    pub name: &'static str,
+    pub fields: Vec<FieldDescription>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldDescription {
+    pub name: &'static str,
+    pub ty: &'static str,
+    pub doc: Option<&'static str>,
+    pub env: Option<String>,
+    pub env_help: Option<&'static str>,
+    /// Dotted through the sections of flattened fields, e.g. `database.host`.
+    pub file_key: String,
+    pub param: Option<&'static str>,
+    pub default: Option<String>,
+    /// Marked #[load_config(required)]: loading fails when no layer sets it.
+    pub required: bool,
+    /// Marked #[load_config(secret)]: redacted in Debug and left out of dumps.
+    pub secret: bool,
+}
+
+/// Backends (parameter stores, secret managers, ...) implement this to feed
+/// fields marked #[load_config(from_param = "...")]; it layers above the file.
+/// With the `ssm` feature, `SsmParameterStore` reads AWS SSM Parameter Store.
+pub trait ConfigSource {
+    fn fetch(&self, key: &str) -> Result<Option<String>, Box<dyn Error>>;
+}
+
+/// One of the places a field's value can come from; Param is a
+/// [`ConfigSource`] and Default the clap default or `Default::default()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Layer {
+    Cli,
+    File,
+    Param,
+    Env,
+    Default,
+}
+
+/// The layer a resolved value came from. Fallback is a warn_on_parse_error
+/// field whose value in the `rejected` layer (Env or File) failed to parse,
+/// so the lower layer `used` supplied it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Cli,
+    File,
+    Param,
+    Env,
+    Default,
+    Fallback {
+        rejected: Layer,
+        used: Layer,
+    },
+}
+
+impl From<Layer> for Source {
+    fn from(layer: Layer) -> Self {
+        match layer {
+            Layer::Cli => Self::Cli,
+            Layer::File => Self::File,
+            Layer::Param => Self::Param,
+            Layer::Env => Self::Env,
+            Layer::Default => Self::Default,
+        }
+    }
+}
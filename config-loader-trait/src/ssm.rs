@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::process::Command;
+
+use crate::ConfigSource;
+
+/// Fetches `from_param` keys from AWS Systems Manager Parameter Store by
+/// running `aws ssm get-parameter`, so the CLI's own profile, region and
+/// credential chain apply and no SDK is linked in. SecureString parameters
+/// are decrypted; a parameter that does not exist leaves the field to the
+/// lower layers.
+#[derive(Clone, Debug)]
+pub struct SsmParameterStore {
+    program: OsString,
+    region: Option<String>,
+    profile: Option<String>,
+}
+
+impl SsmParameterStore {
+    pub fn new() -> Self {
+        Self {
+            program: "aws".into(),
+            region: None,
+            profile: None,
+        }
+    }
+
+    /// Runs this in place of the `aws` found on PATH.
+    pub fn program(mut self, program: impl Into<OsString>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    pub fn region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+}
+
+impl Default for SsmParameterStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigSource for SsmParameterStore {
+    fn fetch(&self, key: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let mut command = Command::new(&self.program);
+        command.args(["ssm", "get-parameter", "--name", key, "--with-decryption", "--query", "Parameter.Value", "--output", "text"]);
+        if let Some(region) = &self.region {
+            command.args(["--region", region]);
+        }
+        if let Some(profile) = &self.profile {
+            command.args(["--profile", profile]);
+        }
+        let output = command.output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            if stderr.contains("ParameterNotFound") {
+                return Ok(None);
+            }
+            return Err(format!("aws ssm get-parameter failed ({}): {}", output.status, stderr.trim()).into());
+        }
+        let value = String::from_utf8(output.stdout)?;
+        Ok(Some(value.strip_suffix('\n').unwrap_or(&value).to_string()))
+    }
+}
@@ -0,0 +1,31 @@
+#![no_main]
+
+use clap::Parser;
+use libfuzzer_sys::fuzz_target;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "config.yml")]
+    config: String,
+
+    #[clap(short, long, default_value = "John")]
+    #[load_config(file_alias = "name")]
+    first_name: String,
+
+    #[clap(short, long, default_value = "42")]
+    age: u8,
+
+    #[clap(long, default_value = "1.5")]
+    ratio: f64,
+
+    #[clap(long, default_value = "false")]
+    verbose: bool,
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(contents) = std::str::from_utf8(data) {
+        let _ = OptsConfigLoaderOpts::from_yaml_str(contents);
+    }
+});
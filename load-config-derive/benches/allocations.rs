@@ -0,0 +1,89 @@
+// Counts the heap allocations made while the layers of a large config are
+// merged and resolved. Each layer's values are moved into the result rather
+// than cloned, so this stays constant however many map entries or how large
+// the buffers are; the full load is shown alongside for scale.
+//
+//     cargo bench -p load-config-derive --bench allocations
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn count<T>(run: impl FnOnce() -> T) -> (T, usize, usize) {
+    let (allocations, bytes) = (ALLOCATIONS.load(Ordering::Relaxed), BYTES.load(Ordering::Relaxed));
+    let value = run();
+    (value, ALLOCATIONS.load(Ordering::Relaxed) - allocations, BYTES.load(Ordering::Relaxed) - bytes)
+}
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, value_parser = parse_entry)]
+    entries: HashMap<String, String>,
+
+    #[clap(long)]
+    buffer: Option<String>,
+
+    #[clap(long)]
+    name: Option<String>,
+}
+
+fn parse_entry(value: &str) -> Result<(String, String), String> {
+    value.split_once('=').map(|(key, value)| (key.to_string(), value.to_string())).ok_or_else(|| format!("expected KEY=VALUE, got {value}"))
+}
+
+fn main() {
+    println!("{:>8}  {:>22}  {:>22}", "entries", "merge + resolve", "load_config_from");
+    for entries in [100, 1_000, 10_000] {
+        let mut file = format!("name: bench\nbuffer: {}\nentries:\n", "x".repeat(1 << 20));
+        for i in 0..entries {
+            file.push_str(&format!("  key{i}: {}\n", "x".repeat(64)));
+        }
+        let args = vec!["bench".to_string()];
+        let env = HashMap::from([("NAME".to_string(), "env".to_string())]);
+
+        let (cli_opts, matches) = OptsConfigLoaderOpts::parse_cli(&args).unwrap();
+        let file_opts = OptsConfigLoaderOpts::from_yaml_str(&file).unwrap();
+        let param_opts = OptsConfigLoaderOpts::default();
+        let env_opts = OptsConfigLoaderOpts::from_env_map(&env, "");
+        let (opts, resolve_allocations, resolve_bytes) = count(|| {
+            let precedence_opts = OptsConfigLoaderOpts::merge(OptsConfigLoaderOpts::merge(file_opts, param_opts), env_opts);
+            OptsConfigLoaderOpts::resolve(&matches, cli_opts, precedence_opts)
+        });
+        assert_eq!(opts.entries.map(|entries| entries.len()), Some(entries));
+
+        let (_, load_allocations, load_bytes) = count(|| Opts::load_config_from(&args, &env, |_| Ok(file.clone())).unwrap());
+        println!(
+            "{entries:>8}  {:>22}  {:>22}",
+            format!("{resolve_allocations} allocs, {resolve_bytes} B"),
+            format!("{load_allocations} allocs, {load_bytes} B"),
+        );
+    }
+}
@@ -0,0 +1,64 @@
+// Loads a config whose HashMap field holds many entries, and times it against
+// parsing the same document once. The load parses it twice (the duplicate key
+// check, then the value) and merge and resolve move each layer's map into the
+// result, so the overhead stays a small constant factor as the map grows.
+//
+//     cargo bench -p load-config-derive --bench large_map
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, value_parser = parse_entry)]
+    entries: HashMap<String, String>,
+
+    #[clap(long)]
+    name: Option<String>,
+}
+
+fn parse_entry(value: &str) -> Result<(String, String), String> {
+    value.split_once('=').map(|(key, value)| (key.to_string(), value.to_string())).ok_or_else(|| format!("expected KEY=VALUE, got {value}"))
+}
+
+const ENTRIES: usize = 20_000;
+const ITERATIONS: u32 = 20;
+
+fn time(iterations: u32, mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        run();
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    let mut file = String::from("name: bench\nentries:\n");
+    for i in 0..ENTRIES {
+        file.push_str(&format!("  key{i}: {}\n", "x".repeat(64)));
+    }
+    let args = vec!["bench".to_string()];
+    let env = HashMap::from([("NAME".to_string(), "env".to_string())]);
+
+    let load = time(ITERATIONS, || {
+        let opts = Opts::load_config_from(&args, &env, |_| Ok(file.clone())).unwrap();
+        assert_eq!(opts.entries.len(), ENTRIES);
+    });
+    let parse = time(ITERATIONS, || {
+        let value: HashMap<String, serde_yaml::Value> = serde_yaml::from_str(&file).unwrap();
+        assert_eq!(value["entries"].as_mapping().map(|entries| entries.len()), Some(ENTRIES));
+    });
+
+    println!("{ENTRIES} map entries, mean of {ITERATIONS} runs");
+    println!("  load_config_from  {load:>12?}");
+    println!("  serde_yaml parse  {parse:>12?}");
+    println!("  overhead          {:>11.2}x", load.as_secs_f64() / parse.as_secs_f64());
+}
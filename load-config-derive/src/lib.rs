@@ -2,12 +2,17 @@
     debug_assertions,
     allow(unused_imports, unused_variables, unused_mut, dead_code, unused_assignments)
 )]
+#![doc = include_str!("../README.md")]
 
 use proc_macro::TokenStream;
-use quote::{format_ident, quote};
-use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Type};
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Field, Fields, Ident, LitStr, Type};
 
-#[proc_macro_derive(LoadConfig)]
+/// Implements `config_loader_trait::ConfigLoader` for a clap `Parser` struct;
+/// see the [crate docs](crate) for the layers and the `#[load_config]`
+/// attributes.
+#[proc_macro_derive(LoadConfig, attributes(load_config))]
 pub fn load_config_derive(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
     let output = impl_config_loader(&ast);
@@ -23,21 +28,747 @@ fn is_option_type(ty: &Type) -> bool {
     false
 }
 
+// The single generic argument of e.g. `Option<T>` or `Vec<T>`, if `ty` is that
+// wrapper.
+fn generic_inner_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let last_segment = type_path.path.segments.last()?;
+    if last_segment.ident != wrapper {
+        return None;
+    }
+    match &last_segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// The element type of a `Vec<T>` or `Option<Vec<T>>` field.
+fn vec_element_type(ty: &Type) -> Option<&Type> {
+    generic_inner_type(generic_inner_type(ty, "Option").unwrap_or(ty), "Vec")
+}
+
+// The key and value types of a `HashMap<K, V>` or `BTreeMap<K, V>` field,
+// optionally wrapped in `Option`.
+fn map_entry_types(ty: &Type) -> Option<(&Type, &Type)> {
+    let Type::Path(type_path) = generic_inner_type(ty, "Option").unwrap_or(ty) else {
+        return None;
+    };
+    let last_segment = type_path.path.segments.last()?;
+    if last_segment.ident != "HashMap" && last_segment.ident != "BTreeMap" {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(args) = &last_segment.arguments else {
+        return None;
+    };
+    let mut types = args.args.iter().filter_map(|arg| match arg {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+    Some((types.next()?, types.next()?))
+}
+
+// Parses an `Option<String>`-like expression into a map field's value from
+// `key1=val1,key2=val2`; an empty string is an empty map. The values of a
+// nested map cannot be written that way, so it is read as a YAML document
+// like `{api: {port: 80}}`.
+fn split_map_value(ty: &Type, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    if is_nested_map_type(ty) {
+        return quote! {
+            #value.and_then(|s| serde_yaml::from_str(s.as_str()).ok())
+        };
+    }
+    quote! {
+        #value.and_then(|s| {
+            s.split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| {
+                    let (key, value) = entry.split_once('=')?;
+                    Some((key.trim().parse().ok()?, value.trim().parse().ok()?))
+                })
+                .collect()
+        })
+    }
+}
+
+// Parses an `Option<String>`-like expression into a list field's value: the
+// string is split on commas, and an empty string is an empty list.
+fn split_list_value(value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    quote! {
+        #value.and_then(|s| s.split(',').map(str::trim).filter(|item| !item.is_empty()).map(|item| item.parse().ok()).collect())
+    }
+}
+
+// Concatenates two layers of a `merge = "append"` list, rhs after lhs, or
+// deep-merges a `merge = "deep"` map.
+fn append_layers(ty: &Type, lhs: proc_macro2::TokenStream, rhs: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let merge = match map_entry_types(ty) {
+        Some((_, value_ty)) => merge_map_entries(value_ty),
+        None => quote! { lhs.extend(rhs); },
+    };
+    quote! {
+        match (#lhs, #rhs) {
+            (Some(mut lhs), Some(rhs)) => {
+                #merge
+                Some(lhs)
+            }
+            (lhs, rhs) => rhs.or(lhs),
+        }
+    }
+}
+
+// Moves the entries of the map `rhs` into the map `lhs`, rhs winning on
+// shared keys, except that values which are maps themselves are merged the
+// same way, level by level, and serde_yaml::Value ones as merge_yaml_values
+// merges files.
+fn merge_map_entries(value_ty: &Type) -> proc_macro2::TokenStream {
+    let merge_value = match map_entry_types(value_ty) {
+        Some((_, inner_ty)) if !is_option_type(value_ty) => {
+            let merge = merge_map_entries(inner_ty);
+            quote! {{
+                let lhs = existing;
+                #merge
+            }}
+        }
+        _ if is_yaml_value_type(value_ty) => quote! { *existing = Self::merge_yaml_values(std::mem::take(existing), rhs) },
+        _ => quote! { *existing = rhs },
+    };
+    quote! {
+        for (key, rhs) in rhs {
+            match lhs.get_mut(&key) {
+                Some(existing) => #merge_value,
+                None => {
+                    lhs.insert(key, rhs);
+                }
+            }
+        }
+    }
+}
+
+// A map field whose values are maps themselves, or serde_yaml::Value.
+fn is_nested_map_type(ty: &Type) -> bool {
+    map_entry_types(ty).is_some_and(|(_, value_ty)| {
+        (map_entry_types(value_ty).is_some() && !is_option_type(value_ty)) || is_yaml_value_type(value_ty)
+    })
+}
+
+fn is_yaml_value_type(ty: &Type) -> bool {
+    let Type::Path(type_path) = ty else {
+        return false;
+    };
+    let segments = type_path.path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>();
+    segments == ["serde_yaml", "Value"]
+}
+
+// The opts type the derive generated for a `#[clap(flatten)]` field's type:
+// `db::DatabaseOpts` (or `Option<db::DatabaseOpts>`) becomes
+// `db::DatabaseOptsConfigLoaderOpts`.
+fn flattened_opts_type(ty: &Type) -> Option<syn::Path> {
+    let Type::Path(type_path) = generic_inner_type(ty, "Option").unwrap_or(ty) else {
+        return None;
+    };
+    let mut path = type_path.path.clone();
+    let last_segment = path.segments.last_mut()?;
+    last_segment.ident = format_ident!("{}ConfigLoaderOpts", last_segment.ident);
+    Some(path)
+}
+
+fn is_bool_type(ty: &Type) -> bool {
+    matches!(ty, Type::Path(type_path) if type_path.path.is_ident("bool"))
+}
+
+fn is_string_type(ty: &Type) -> bool {
+    matches!(ty, Type::Path(type_path) if type_path.path.segments.last().is_some_and(|segment| segment.ident == "String"))
+}
+
+// `config: String`, or `config: Vec<String>` for layered config files.
+fn is_config_field(field: &Field) -> bool {
+    if let Some(ident) = &field.ident {
+        if ident == "config" {
+            if let syn::Type::Path(type_path) = &field.ty {
+                return type_path.path.is_ident("String") || is_config_list_field(field);
+            }
+        }
+    }
+    false
+}
+
+fn is_config_list_field(field: &Field) -> bool {
+    field.ident.as_ref().is_some_and(|ident| ident == "config")
+        && generic_inner_type(&field.ty, "Vec").is_some_and(|inner| matches!(inner, Type::Path(type_path) if type_path.path.is_ident("String")))
+}
+
+// The id clap registers the field's arg under: the field name, unless its
+// clap attributes set `id = "..."` (or the older `name = "..."`).
+fn clap_arg_id(field: &Field) -> String {
+    field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("clap") || attr.path().is_ident("arg"))
+        .filter_map(|attr| {
+            attr.parse_args_with(syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .find_map(|meta| match meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                path,
+                value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(id), .. }),
+                ..
+            }) if path.is_ident("id") || path.is_ident("name") => Some(id.value()),
+            _ => None,
+        })
+        .unwrap_or_else(|| field.ident.as_ref().unwrap().to_string())
+}
+
+// Renders a type the way it would be written in source, e.g. "Option<String>"
+// rather than the token stream's "Option < String >".
+fn type_name(ty: &Type) -> String {
+    quote!(#ty)
+        .to_string()
+        .replace(" :: ", "::")
+        .replace(" <", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+}
+
+// #[cfg] on a field has to be repeated on every piece of per-field code the
+// derive emits, so the generated items compile whether or not it is active.
+fn cfg_attrs(field: &Field) -> Vec<&syn::Attribute> {
+    field.attrs.iter().filter(|attr| attr.path().is_ident("cfg")).collect()
+}
+
+fn doc_lines(field: &Field) -> Vec<LitStr> {
+    field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(doc), .. }),
+                ..
+            }) => Some(doc.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+// The key serde deserializes this field from, when it is renamed with
+// `#[serde(rename = "...")]` or `#[serde(rename(deserialize = "..."))]`.
+fn serde_rename(field: &Field) -> Option<String> {
+    let str_value = |expr: &syn::Expr| match expr {
+        syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(value), .. }) => Some(value.value()),
+        _ => None,
+    };
+    field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("serde"))
+        .filter_map(|attr| {
+            attr.parse_args_with(syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .find_map(|meta| match meta {
+            syn::Meta::NameValue(rename) if rename.path.is_ident("rename") => str_value(&rename.value),
+            syn::Meta::List(rename) if rename.path.is_ident("rename") => rename
+                .parse_args_with(syn::punctuated::Punctuated::<syn::MetaNameValue, syn::Token![,]>::parse_terminated)
+                .ok()?
+                .iter()
+                .find(|direction| direction.path.is_ident("deserialize"))
+                .and_then(|direction| str_value(&direction.value)),
+            _ => None,
+        })
+}
+
+// Accepts a plain byte count or a number with a decimal (KB, MB, GB) or
+// binary (KiB, MiB, GiB) suffix, e.g. "1MiB" or "512 KB".
+fn parse_byte_size(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let multiplier = match unit.trim() {
+        "" | "B" => 1,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+#[derive(Clone, Copy)]
+enum Case {
+    Lower,
+    Upper,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+    Camel,
+    Pascal,
+}
+
+impl Case {
+    const NAMES: &'static str = "lower, upper, snake, screaming_snake, kebab, screaming_kebab, camel, pascal";
+
+    fn parse(name: &LitStr) -> syn::Result<Self> {
+        match name.value().as_str() {
+            "lower" => Ok(Self::Lower),
+            "upper" => Ok(Self::Upper),
+            "snake" => Ok(Self::Snake),
+            "screaming_snake" => Ok(Self::ScreamingSnake),
+            "kebab" => Ok(Self::Kebab),
+            "screaming_kebab" => Ok(Self::ScreamingKebab),
+            "camel" => Ok(Self::Camel),
+            "pascal" => Ok(Self::Pascal),
+            _ => Err(syn::Error::new(name.span(), format!("unknown case, expected one of: {}", Self::NAMES))),
+        }
+    }
+
+    // The equivalent serde rename_all rule, so file keys stay in sync with
+    // what apply() computes for a snake_case field name.
+    fn serde_rule(self) -> &'static str {
+        match self {
+            Self::Lower => "lowercase",
+            Self::Upper => "UPPERCASE",
+            Self::Snake => "snake_case",
+            Self::ScreamingSnake => "SCREAMING_SNAKE_CASE",
+            Self::Kebab => "kebab-case",
+            Self::ScreamingKebab => "SCREAMING-KEBAB-CASE",
+            Self::Camel => "camelCase",
+            Self::Pascal => "PascalCase",
+        }
+    }
+
+    fn apply(self, field_name: &str) -> String {
+        match self {
+            Self::Lower | Self::Snake => field_name.to_string(),
+            Self::Upper | Self::ScreamingSnake => field_name.to_ascii_uppercase(),
+            Self::Kebab => field_name.replace('_', "-"),
+            Self::ScreamingKebab => field_name.replace('_', "-").to_ascii_uppercase(),
+            Self::Pascal | Self::Camel => {
+                let mut converted = String::new();
+                let mut capitalize = matches!(self, Self::Pascal);
+                for ch in field_name.chars() {
+                    if ch == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        converted.push(ch.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        converted.push(ch);
+                    }
+                }
+                converted
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum UndefinedVars {
+    Literal,
+    Error,
+}
+
+// Which end of a `config: Vec<String>` list wins when files set the same key.
+enum MultiConfigOrder {
+    LastWins,
+    FirstWins,
+}
+
+struct StructAttrs {
+    max_file_size: Option<u64>,
+    template: Option<LitStr>,
+    verify: Option<LitStr>,
+    dated_config_glob: Option<LitStr>,
+    expand_strings: Option<UndefinedVars>,
+    env_allowlist: Option<Vec<LitStr>>,
+    field_refs: bool,
+    config_required: Option<proc_macro2::Span>,
+    env_prefix: String,
+    app_name: Option<LitStr>,
+    validate: Option<syn::Path>,
+    profile_field: Option<LitStr>,
+    dotenv: Option<LitStr>,
+    env_case: Case,
+    file_case: Case,
+    multi_config_order: Option<(MultiConfigOrder, proc_macro2::Span)>,
+    debug: bool,
+}
+
+impl Default for StructAttrs {
+    fn default() -> Self {
+        Self {
+            max_file_size: None,
+            template: None,
+            verify: None,
+            dated_config_glob: None,
+            expand_strings: None,
+            env_allowlist: None,
+            field_refs: false,
+            config_required: None,
+            env_prefix: String::new(),
+            app_name: None,
+            validate: None,
+            profile_field: None,
+            dotenv: None,
+            env_case: Case::ScreamingSnake,
+            file_case: Case::Kebab,
+            multi_config_order: None,
+            debug: false,
+        }
+    }
+}
+
+impl StructAttrs {
+    // The env var a field is read from, without the env prefix: an explicit
+    // `env = "..."` wins, then the serde rename, then the field name.
+    fn env_name(&self, field: &Field, attrs: &FieldAttrs) -> String {
+        match &attrs.env {
+            Some(env) => env.value(),
+            None => {
+                let name = serde_rename(field).unwrap_or_else(|| field.ident.as_ref().unwrap().to_string());
+                self.env_case.apply(&name)
+            }
+        }
+    }
+
+    // The key a field is read from in the config file: its serde rename, or
+    // the field name in file_case.
+    fn file_key(&self, field: &Field) -> String {
+        serde_rename(field).unwrap_or_else(|| self.file_case.apply(&field.ident.as_ref().unwrap().to_string()))
+    }
+
+    // Whether the env layer is consulted for a field with this (unprefixed)
+    // env var name; everything is when there is no env_allowlist.
+    fn env_enabled(&self, env_name: &str) -> bool {
+        match &self.env_allowlist {
+            Some(names) => names.iter().any(|name| name.value() == env_name),
+            None => true,
+        }
+    }
+
+    fn from_ast(ast: &DeriveInput) -> syn::Result<Self> {
+        let mut struct_attrs = Self::default();
+        for attr in ast.attrs.iter().filter(|attr| attr.path().is_ident("load_config")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("max_file_size") {
+                    let value: LitStr = meta.value()?.parse()?;
+                    let size = parse_byte_size(&value.value())
+                        .ok_or_else(|| syn::Error::new(value.span(), "invalid size, expected e.g. \"1MiB\" or \"4096\""))?;
+                    struct_attrs.max_file_size = Some(size);
+                    Ok(())
+                } else if meta.path.is_ident("template") {
+                    let engine: LitStr = meta.value()?.parse()?;
+                    if engine.value() != "minijinja" {
+                        return Err(syn::Error::new(engine.span(), "unsupported template engine, expected \"minijinja\""));
+                    }
+                    struct_attrs.template = Some(engine);
+                    Ok(())
+                } else if meta.path.is_ident("verify") {
+                    let algorithm: LitStr = meta.value()?.parse()?;
+                    if algorithm.value() != "sha256" {
+                        return Err(syn::Error::new(algorithm.span(), "unsupported verify algorithm, expected \"sha256\""));
+                    }
+                    struct_attrs.verify = Some(algorithm);
+                    Ok(())
+                } else if meta.path.is_ident("dated_config_glob") {
+                    let glob: LitStr = meta.value()?.parse()?;
+                    let value = glob.value();
+                    let file_name = value.rsplit('/').next().unwrap_or_default();
+                    if value.matches('*').count() != 1 || !file_name.contains('*') {
+                        return Err(syn::Error::new(glob.span(), "dated_config_glob needs exactly one `*`, in the file name"));
+                    }
+                    struct_attrs.dated_config_glob = Some(glob);
+                    Ok(())
+                } else if meta.path.is_ident("expand_strings") {
+                    struct_attrs.expand_strings = Some(UndefinedVars::Literal);
+                    if meta.input.peek(syn::Token![=]) {
+                        let mode: LitStr = meta.value()?.parse()?;
+                        struct_attrs.expand_strings = Some(match mode.value().as_str() {
+                            "literal" => UndefinedVars::Literal,
+                            "error" => UndefinedVars::Error,
+                            _ => return Err(syn::Error::new(mode.span(), "expected \"literal\" or \"error\"")),
+                        });
+                    }
+                    Ok(())
+                } else if meta.path.is_ident("env_allowlist") {
+                    let names: syn::ExprArray = meta.value()?.parse()?;
+                    let names = names
+                        .elems
+                        .iter()
+                        .map(|name| match name {
+                            syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(name), .. }) => Ok(name.clone()),
+                            other => Err(syn::Error::new(other.span(), "expected a string literal env var name")),
+                        })
+                        .collect::<syn::Result<_>>()?;
+                    struct_attrs.env_allowlist = Some(names);
+                    Ok(())
+                } else if meta.path.is_ident("config_required") {
+                    struct_attrs.config_required = Some(meta.path.span());
+                    Ok(())
+                } else if meta.path.is_ident("field_refs") {
+                    struct_attrs.field_refs = true;
+                    Ok(())
+                } else if meta.path.is_ident("debug") {
+                    struct_attrs.debug = true;
+                    Ok(())
+                } else if meta.path.is_ident("env_prefix") {
+                    struct_attrs.env_prefix = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else if meta.path.is_ident("validate") {
+                    struct_attrs.validate = Some(meta.value()?.parse::<LitStr>()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("dotenv") {
+                    struct_attrs.dotenv = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("profile_field") {
+                    struct_attrs.profile_field = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("app_name") {
+                    struct_attrs.app_name = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("env_case") {
+                    struct_attrs.env_case = Case::parse(&meta.value()?.parse()?)?;
+                    Ok(())
+                } else if meta.path.is_ident("file_case") {
+                    struct_attrs.file_case = Case::parse(&meta.value()?.parse()?)?;
+                    Ok(())
+                } else if meta.path.is_ident("multi_config_order") {
+                    let order: LitStr = meta.value()?.parse()?;
+                    let value = match order.value().as_str() {
+                        "last_wins" => MultiConfigOrder::LastWins,
+                        "first_wins" => MultiConfigOrder::FirstWins,
+                        _ => return Err(syn::Error::new(order.span(), "expected \"last_wins\" or \"first_wins\"")),
+                    };
+                    struct_attrs.multi_config_order = Some((value, order.span()));
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported load_config struct attribute"))
+                }
+            })?;
+        }
+        Ok(struct_attrs)
+    }
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    from_param: Option<LitStr>,
+    file_aliases: Vec<LitStr>,
+    env_help: Option<LitStr>,
+    env: Option<LitStr>,
+    append: bool,
+    required: bool,
+    skip: bool,
+    flatten: bool,
+    secret: bool,
+    dynamic_values_from: Option<LitStr>,
+    warn_on_parse_error: bool,
+}
+
+impl FieldAttrs {
+    fn from_field(field: &Field) -> syn::Result<Self> {
+        let mut field_attrs = Self::default();
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("load_config")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("from_param") {
+                    field_attrs.from_param = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("env") {
+                    field_attrs.env = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("skip") {
+                    field_attrs.skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("flatten") {
+                    field_attrs.flatten = true;
+                    Ok(())
+                } else if meta.path.is_ident("secret") {
+                    field_attrs.secret = true;
+                    Ok(())
+                } else if meta.path.is_ident("dynamic_values_from") {
+                    field_attrs.dynamic_values_from = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("warn_on_parse_error") {
+                    field_attrs.warn_on_parse_error = true;
+                    Ok(())
+                } else if meta.path.is_ident("required") {
+                    if is_option_type(&field.ty) {
+                        return Err(meta.error("required has no effect on an Option field"));
+                    }
+                    field_attrs.required = true;
+                    Ok(())
+                } else if meta.path.is_ident("merge") {
+                    let strategy: LitStr = meta.value()?.parse()?;
+                    field_attrs.append = match strategy.value().as_str() {
+                        "append" if vec_element_type(&field.ty).is_some() => true,
+                        "append" => return Err(syn::Error::new(strategy.span(), "merge = \"append\" needs a Vec field")),
+                        "deep" if map_entry_types(&field.ty).is_some() => true,
+                        "deep" => return Err(syn::Error::new(strategy.span(), "merge = \"deep\" needs a HashMap or BTreeMap field")),
+                        "replace" => false,
+                        _ => return Err(syn::Error::new(strategy.span(), "expected \"append\", \"deep\" or \"replace\"")),
+                    };
+                    Ok(())
+                } else if meta.path.is_ident("env_help") {
+                    field_attrs.env_help = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else if meta.path.is_ident("file_alias") {
+                    field_attrs.file_aliases.push(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported load_config field attribute"))
+                }
+            })?;
+        }
+        // A field clap flattens is a nested struct of options, so it is loaded
+        // through the opts the nested type's own LoadConfig derive generated.
+        let clap_flatten = field.attrs.iter().filter(|attr| attr.path().is_ident("clap") || attr.path().is_ident("command")).any(|attr| {
+            attr.parse_args_with(syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated)
+                .is_ok_and(|metas| metas.iter().any(|meta| meta.path().is_ident("flatten")))
+        });
+        field_attrs.flatten |= clap_flatten;
+        if field_attrs.flatten {
+            if field_attrs.env.is_some()
+                || field_attrs.from_param.is_some()
+                || field_attrs.append
+                || field_attrs.required
+                || field_attrs.secret
+                || field_attrs.dynamic_values_from.is_some()
+                || field_attrs.warn_on_parse_error
+                || !field_attrs.file_aliases.is_empty()
+            {
+                return Err(syn::Error::new(field.span(), "a flattened field takes its settings from the nested type's own attributes"));
+            }
+            if flattened_opts_type(&field.ty).is_none() {
+                return Err(syn::Error::new(field.ty.span(), "a flattened field needs a struct type that also derives LoadConfig"));
+            }
+        }
+        Ok(field_attrs)
+    }
+}
+
 fn impl_config_loader(ast: &DeriveInput) -> proc_macro2::TokenStream {
     let struct_name = &ast.ident;
-    let config_loader_opts_ident = format_ident!("ConfigLoaderOpts");
+    let struct_vis = &ast.vis;
+    // Named after the struct and as visible as it, so a struct that flattens
+    // this one into its own options can reach it. Everything shared between
+    // derives lives in config_loader_trait and is referred to by path.
+    let config_loader_opts_ident = format_ident!("{}ConfigLoaderOpts", struct_name);
 
     let fields = match &ast.data {
         Data::Struct(data) => match &data.fields {
             Fields::Named(fields) => fields,
-            _ => unimplemented!("ConfigLoader only supports structs with named fields."),
+            fields => return syn::Error::new(fields.span(), "LoadConfig only supports structs with named fields").to_compile_error(),
         },
-        _ => unimplemented!("ConfigLoader can only be derived for structs."),
+        _ => return syn::Error::new(struct_name.span(), "LoadConfig can only be derived for structs").to_compile_error(),
+    };
+
+    let struct_attrs = match StructAttrs::from_ast(ast) {
+        Ok(struct_attrs) => struct_attrs,
+        Err(err) => return err.to_compile_error(),
+    };
+
+    let field_attrs = match fields.named.iter().map(FieldAttrs::from_field).collect::<syn::Result<Vec<_>>>() {
+        Ok(field_attrs) => field_attrs,
+        Err(err) => return err.to_compile_error(),
+    };
+
+    // With #[load_config(debug)] the derive implements Debug for the struct
+    // itself, printing #[load_config(secret)] fields as "***", in place of
+    // #[derive(Debug)].
+    let struct_debug_impl = if struct_attrs.debug {
+        let debug_fields = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+            let name = &field.ident;
+            let name_str = name.as_ref().unwrap().to_string();
+            let cfg_attrs = cfg_attrs(field);
+            let value = if attrs.secret {
+                quote! { &"***" }
+            } else {
+                quote! { &self.#name }
+            };
+            quote! {
+                #(#cfg_attrs)*
+                debug.field(#name_str, #value);
+            }
+        });
+        let struct_name_str = struct_name.to_string();
+        quote! {
+            impl std::fmt::Debug for #struct_name {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    let mut debug = f.debug_struct(#struct_name_str);
+                    #(#debug_fields)*
+                    debug.finish()
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    // Skipped fields take no part in loading: everything below sees only the
+    // loaded fields, and the final conversion fills skipped ones with Default.
+    let skipped_fields = fields.named.iter().zip(&field_attrs).filter(|(_, attrs)| attrs.skip).map(|(field, _)| field).collect::<Vec<_>>();
+    let (loaded_fields, field_attrs): (syn::punctuated::Punctuated<Field, syn::Token![,]>, Vec<_>) =
+        fields.named.iter().cloned().zip(field_attrs).filter(|(_, attrs)| !attrs.skip).unzip();
+    let fields = &syn::FieldsNamed {
+        brace_token: fields.brace_token,
+        named: loaded_fields,
+    };
+
+    if let Some(names) = &struct_attrs.env_allowlist {
+        let env_names = fields
+            .named
+            .iter()
+            .zip(&field_attrs)
+            .filter(|(_, attrs)| !attrs.flatten)
+            .map(|(field, attrs)| struct_attrs.env_name(field, attrs))
+            .collect::<Vec<_>>();
+        if let Some(unknown) = names.iter().find(|name| !env_names.contains(&name.value())) {
+            return syn::Error::new(unknown.span(), format!("no field reads env var `{}`", unknown.value())).to_compile_error();
+        }
+    }
+
+    // The nested opts type of a flattened field, for the generators that only
+    // walk the fields.
+    let flattened = |field: &Field| {
+        fields
+            .named
+            .iter()
+            .zip(&field_attrs)
+            .find(|(candidate, attrs)| attrs.flatten && candidate.ident == field.ident)
+            .and_then(|(field, _)| flattened_opts_type(&field.ty))
     };
 
-    let config_loader_opts_fields = fields.named.iter().map(|field| {
+    let config_loader_opts_fields = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
         let name = &field.ident;
         let ty = &field.ty;
+        // A flattened field holds the nested type's opts, which carry their own
+        // clap args and read a nested section of the file. The nested type must
+        // also derive LoadConfig and be named by a path its generated opts
+        // share.
+        if attrs.flatten {
+            let opts_ty = flattened_opts_type(ty);
+            let file_key = struct_attrs.file_key(field);
+            let cfg_attrs = cfg_attrs(field);
+            return quote! {
+                #(#cfg_attrs)*
+                #[command(flatten)]
+                #[serde(default, rename = #file_key)]
+                pub #name: #opts_ty,
+            };
+        }
         let option_ty = if is_option_type(ty) {
             quote! { #ty }
         } else {
@@ -55,187 +786,2057 @@ fn impl_config_loader(ast: &DeriveInput) -> proc_macro2::TokenStream {
                 },
             );
 
+        let file_aliases = &attrs.file_aliases;
+        let file_key = struct_attrs.file_key(field);
+        let cfg_attrs = cfg_attrs(field);
+
         quote! {
+            #(#cfg_attrs)*
             #(#clap_attrs)*
+            #[serde(rename = #file_key)]
+            #(#[serde(alias = #file_aliases)])*
             pub #name: #option_ty,
         }
     });
 
-    let config_loader_trait = quote! {
-        trait ConfigLoader: Sized {
-            fn load_config() -> Result<Self, Box<dyn std::error::Error>>;
-        }
-    };
-
     let merge_function = {
-        let field_merges = fields.named.iter().map(|field| {
+        let field_merges = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
             let name = &field.ident;
+            let cfg_attrs = cfg_attrs(field);
+            let name_str = name.as_ref().unwrap().to_string();
+            let merged = if attrs.flatten {
+                let opts_ty = flattened_opts_type(&field.ty);
+                quote! { #opts_ty::merge(lhs.#name, rhs.#name) }
+            } else if attrs.append {
+                let appended = append_layers(&field.ty, quote! { lhs.#name }, quote! { rhs.#name });
+                quote! { if rhs.cleared.contains(&#name_str) { None } else { #appended } }
+            } else {
+                quote! { if rhs.cleared.contains(&#name_str) { None } else { rhs.#name.or(lhs.#name) } }
+            };
             quote! {
-                #name: rhs.#name.clone().or_else(|| lhs.#name.clone()),
+                #(#cfg_attrs)*
+                #name: #merged,
             }
         });
 
         quote! {
-            pub fn merge(lhs: &Self, rhs: &Self) -> Self {
+            pub fn merge(lhs: Self, rhs: Self) -> Self {
                 Self {
                     #(#field_merges)*
+                    parse_fallbacks: [lhs.parse_fallbacks, rhs.parse_fallbacks].concat(),
+                    cleared: rhs.cleared,
+                }
+            }
+        }
+    };
+
+    // The highest layer that supplied each field's value, with the CLI counted
+    // the same way resolve picks it, or a Fallback when a layer above it held a
+    // value that failed to parse. A flattened field's entries come from the
+    // nested type's own sources, under dotted names like `database.host`.
+    let value_sources_function = {
+        let field_sources = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+            let name = &field.ident;
+            let name_str = name.as_ref().unwrap().to_string();
+            let id = clap_arg_id(field);
+            let cfg_attrs = cfg_attrs(field);
+            if attrs.flatten {
+                let opts_ty = flattened_opts_type(&field.ty);
+                return quote! {
+                    #(#cfg_attrs)*
+                    for (name, source) in #opts_ty::value_sources(matches, &file_opts.#name, &param_opts.#name, &env_opts.#name) {
+                        sources.insert(format!("{}.{}", #name_str, name), source);
+                    }
+                };
+            }
+            quote! {
+                #(#cfg_attrs)*
+                layers.push((
+                    #name_str,
+                    if Self::on_command_line(matches, #id) {
+                        ::config_loader_trait::Layer::Cli
+                    } else if env_opts.#name.is_some() {
+                        ::config_loader_trait::Layer::Env
+                    } else if param_opts.#name.is_some() {
+                        ::config_loader_trait::Layer::Param
+                    } else if file_opts.#name.is_some() {
+                        ::config_loader_trait::Layer::File
+                    } else {
+                        ::config_loader_trait::Layer::Default
+                    },
+                ));
+            }
+        });
+
+        quote! {
+            pub fn value_sources(
+                matches: &clap::ArgMatches,
+                file_opts: &Self,
+                param_opts: &Self,
+                env_opts: &Self,
+            ) -> std::collections::HashMap<String, ::config_loader_trait::Source> {
+                let mut sources = std::collections::HashMap::new();
+                let mut layers = Vec::new();
+                #(#field_sources)*
+                for (name, used) in layers {
+                    let rejected = env_opts
+                        .parse_fallbacks
+                        .iter()
+                        .chain(&file_opts.parse_fallbacks)
+                        .filter(|(rejected_name, _)| *rejected_name == name)
+                        .map(|(_, rejected)| *rejected)
+                        .find(|rejected| {
+                            matches!(
+                                (rejected, used),
+                                (::config_loader_trait::Layer::Env, ::config_loader_trait::Layer::Param | ::config_loader_trait::Layer::File | ::config_loader_trait::Layer::Default)
+                                    | (::config_loader_trait::Layer::File, ::config_loader_trait::Layer::Default)
+                            )
+                        });
+                    let source = match rejected {
+                        Some(rejected) => ::config_loader_trait::Source::Fallback { rejected, used },
+                        None => used.into(),
+                    };
+                    sources.insert(name.to_string(), source);
                 }
+                sources
             }
         }
     };
 
     let resolve_function = {
-        let field_resolutions = fields.named.iter().map(|field| {
+        let field_resolutions = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
             let name = &field.ident;
+            let cfg_attrs = cfg_attrs(field);
+            if attrs.flatten && is_option_type(&field.ty) {
+                let opts_ty = flattened_opts_type(&field.ty);
+                return quote! {
+                    #(#cfg_attrs)*
+                    #name: if #opts_ty::passed_on_command_line(matches) || !precedence_opts.#name.is_empty() {
+                        #opts_ty::resolve(matches, cli_opts.#name, precedence_opts.#name)
+                    } else {
+                        #opts_ty::default()
+                    },
+                };
+            }
+            if attrs.flatten {
+                let opts_ty = flattened_opts_type(&field.ty);
+                return quote! {
+                    #(#cfg_attrs)*
+                    #name: #opts_ty::resolve(matches, cli_opts.#name, precedence_opts.#name),
+                };
+            }
+            let cli_value = if attrs.append {
+                append_layers(&field.ty, quote! { precedence_opts.#name }, quote! { cli_opts.#name })
+            } else {
+                quote! { cli_opts.#name }
+            };
+            let id = clap_arg_id(field);
             quote! {
-                #name: if cli_opts.#name.as_ref() != default_value_opts.#name.as_ref() {
-                    cli_opts.#name.clone()
+                #(#cfg_attrs)*
+                #name: if Self::on_command_line(matches, #id) {
+                    #cli_value
                 } else {
-                    precedence_opts.#name.clone()
+                    precedence_opts.#name.or(cli_opts.#name)
                 },
             }
         });
 
+        let passed_checks = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+            let cfg_attrs = cfg_attrs(field);
+            let passed = if attrs.flatten {
+                let opts_ty = flattened_opts_type(&field.ty);
+                quote! { #opts_ty::passed_on_command_line(matches) }
+            } else {
+                let id = clap_arg_id(field);
+                quote! { Self::on_command_line(matches, #id) }
+            };
+            quote! {
+                #(#cfg_attrs)*
+                if #passed {
+                    return true;
+                }
+            }
+        });
+        let set_checks = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+            let name = &field.ident;
+            let cfg_attrs = cfg_attrs(field);
+            let set = if attrs.flatten {
+                quote! { !self.#name.is_empty() }
+            } else {
+                quote! { self.#name.is_some() }
+            };
+            quote! {
+                #(#cfg_attrs)*
+                if #set {
+                    return false;
+                }
+            }
+        });
+
+        // The CLI wins for the args actually passed on the command line, even
+        // when the value equals the default; every other field of cli_opts
+        // holds its clap default, the fallback when no layer sets it.
         quote! {
-            pub fn resolve(cli_opts: &Self, default_value_opts: &Self, precedence_opts: &Self) -> Self {
+            pub fn parse_cli(args: &[String]) -> Result<(Self, clap::ArgMatches), ::config_loader_trait::ConfigError> {
+                let matches = <Self as clap::CommandFactory>::command().try_get_matches_from(args)?;
+                let cli_opts = <Self as clap::FromArgMatches>::from_arg_matches(&matches)?;
+                Ok((cli_opts, matches))
+            }
+
+            // The loaders that read the process argv behave like clap's parse():
+            // bad arguments, --help and --version print and exit.
+            pub fn exit_on_args_error<T>(result: Result<T, ::config_loader_trait::ConfigError>) -> Result<T, ::config_loader_trait::ConfigError> {
+                if let Err(::config_loader_trait::ConfigError::Args(err)) = &result {
+                    err.exit();
+                }
+                result
+            }
+
+            // Fields that are not clap args (#[clap(skip)]) are never on the
+            // command line; value_source would panic on their ids in debug builds.
+            pub fn on_command_line(matches: &clap::ArgMatches, id: &str) -> bool {
+                matches.ids().any(|matched| matched.as_str() == id)
+                    && matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine)
+            }
+
+            // An optional flattened block (`Option<Nested>`) is present when
+            // one of its args is on the command line or the file, a param or the
+            // env sets one of its fields; clap defaults alone leave it None.
+            pub fn resolve(matches: &clap::ArgMatches, cli_opts: Self, precedence_opts: Self) -> Self {
                 Self {
                     #(#field_resolutions)*
+                    parse_fallbacks: precedence_opts.parse_fallbacks,
+                    cleared: precedence_opts.cleared,
                 }
             }
+
+            pub fn passed_on_command_line(matches: &clap::ArgMatches) -> bool {
+                #(#passed_checks)*
+                false
+            }
+
+            pub fn is_empty(&self) -> bool {
+                #(#set_checks)*
+                true
+            }
         }
     };
 
+    // With #[load_config(dotenv = ".env")] the KEY=VALUE lines of that file,
+    // relative to the working directory, are read into the env layer beneath
+    // the real environment, so a process env var wins over its .env entry. A
+    // missing file is the same as an empty one.
+    let env_layer_function = match &struct_attrs.dotenv {
+        Some(dotenv) => quote! {
+            pub fn env_layer(ctx: &::config_loader_trait::LoadContext, prefix: &str) -> Result<Self, ::config_loader_trait::ConfigError> {
+                let dotenv_path = ctx.cwd.join(#dotenv);
+                let mut env = match ctx.read_to_string(&dotenv_path) {
+                    Ok(contents) => Self::parse_dotenv(&dotenv_path, &contents)?,
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => std::collections::HashMap::new(),
+                    Err(err) => return Err(err.into()),
+                };
+                env.extend(ctx.env.iter().map(|(key, value)| (key.clone(), value.clone())));
+                Ok(Self::from_env_map(&env, prefix))
+            }
+
+            // Blank lines and `#` comments are skipped, an `export ` prefix is
+            // allowed, and a value wrapped in matching quotes is unwrapped.
+            pub fn parse_dotenv(path: &std::path::Path, contents: &str) -> Result<std::collections::HashMap<String, String>, ::config_loader_trait::ConfigError> {
+                let mut env = std::collections::HashMap::new();
+                for (index, line) in contents.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let line = line.strip_prefix("export ").unwrap_or(line);
+                    let Some((key, value)) = line.split_once('=') else {
+                        return Err(::config_loader_trait::ConfigError::Invalid(format!("{}:{}: expected KEY=VALUE", path.display(), index + 1)));
+                    };
+                    let value = value.trim();
+                    let value = ['"', '\'']
+                        .iter()
+                        .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+                        .unwrap_or(value);
+                    env.insert(key.trim().to_string(), value.to_string());
+                }
+                Ok(env)
+            }
+        },
+        None => quote! {
+            pub fn env_layer(ctx: &::config_loader_trait::LoadContext, prefix: &str) -> Result<Self, ::config_loader_trait::ConfigError> {
+                Ok(Self::from_env_map(&ctx.env, prefix))
+            }
+        },
+    };
+
+    let env_prefix = &struct_attrs.env_prefix;
     let from_env_function = {
-        let env_assignments = fields.named.iter().map(|field| {
+        let env_assignments = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
             let ident = &field.ident;
-            let ident_str = ident.as_ref().unwrap().to_string().to_uppercase();
+            let ident_str = struct_attrs.env_name(field, attrs);
             let ty = &field.ty;
-            let option_wrapped = is_option_type(ty);
+            let string_option = generic_inner_type(ty, "Option").is_some_and(is_string_type);
+
+            // An explicit env name points at an existing variable, so the
+            // prefix is not applied to it.
+            let env_key = if attrs.env.is_some() {
+                quote! { #ident_str }
+            } else {
+                quote! { &format!("{}{}", prefix, #ident_str) }
+            };
 
-            let env_var_assignment = if option_wrapped {
+            // A nested struct's fields are read under `PREFIX_FIELD__`, so
+            // `database.host` is `PREFIX_DATABASE__HOST`; an explicit env name
+            // in the nested struct still bypasses it.
+            let env_var_assignment = if attrs.flatten {
+                let opts_ty = flattened_opts_type(ty);
+                quote! {
+                    #opts_ty::from_env_map(env, &format!("{}{}__", prefix, #ident_str))
+                }
+            } else if !struct_attrs.env_enabled(&ident_str) {
+                quote! {
+                    None
+                }
+            } else if vec_element_type(ty).is_some() {
+                split_list_value(quote! { env.get(#env_key) })
+            } else if map_entry_types(ty).is_some() {
+                split_map_value(ty, quote! { env.get(#env_key) })
+            } else if string_option {
+                quote! {
+                    env.get(#env_key).cloned()
+                }
+            } else if is_bool_type(generic_inner_type(ty, "Option").unwrap_or(ty)) {
                 quote! {
-                    std::env::var(#ident_str).ok()
+                    env.get(#env_key).and_then(|s| Self::parse_bool(s))
                 }
             } else {
                 quote! {
-                    std::env::var(#ident_str).ok().and_then(|s| s.parse().ok())
+                    env.get(#env_key).and_then(|s| s.parse().ok())
+                }
+            };
+
+            // With warn_on_parse_error a set env var that does not parse is
+            // reported, and the field falls back to the lower layers.
+            let env_var_assignment = if attrs.warn_on_parse_error {
+                let name_str = field.ident.as_ref().unwrap().to_string();
+                quote! {
+                    {
+                        let value = #env_var_assignment;
+                        if value.is_none() && env.contains_key(#env_key) {
+                            eprintln!("warning: env var {} has an invalid value for `{}`, ignoring it", #env_key, #name_str);
+                            parse_fallbacks.push((#name_str, ::config_loader_trait::Layer::Env));
+                        }
+                        value
+                    }
                 }
+            } else {
+                env_var_assignment
             };
 
+            let cfg_attrs = cfg_attrs(field);
+
             quote! {
+                #(#cfg_attrs)*
                 #ident: #env_var_assignment
             }
         });
 
         quote! {
             pub fn from_env() -> Self {
+                Self::from_env_with_prefix(#env_prefix)
+            }
+
+            pub fn from_env_with_prefix(prefix: &str) -> Self {
+                Self::from_env_map(&::config_loader_trait::LoadContext::from_process().env, prefix)
+            }
+
+            pub fn from_env_map(env: &std::collections::HashMap<String, String>, prefix: &str) -> Self {
+                let mut parse_fallbacks = Vec::new();
                 Self {
-                    #(#env_assignments),*
+                    #(#env_assignments,)*
+                    parse_fallbacks,
+                    cleared: Vec::new(),
+                }
+            }
+
+            #env_layer_function
+
+            // The usual env spellings of a flag; anything else leaves the field
+            // to the other layers.
+            pub fn parse_bool(value: &str) -> Option<bool> {
+                match value.trim().to_ascii_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => Some(true),
+                    "0" | "false" | "no" | "off" => Some(false),
+                    _ => None,
                 }
             }
         }
     };
 
-    let load_yaml_function = quote! {
-        pub fn load_yaml(config_path: Option<&str>, default_value_opts: &Self) -> Self {
-            if let Some(config_path) = config_path {
-                if std::path::Path::new(config_path).exists() {
-                    match std::fs::read_to_string(config_path) {
-                        Ok(config_contents) => match serde_yaml::from_str(&config_contents) {
-                            Ok(yml_opts) => yml_opts,
-                            Err(_) => default_value_opts.clone(),
-                        },
-                        Err(_) => default_value_opts.clone(),
-                    }
-                } else {
-                    default_value_opts.clone()
+    let from_source_function = {
+        let param_assignments = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+            let ident = &field.ident;
+            let value = match &attrs.from_param {
+                Some(key) if vec_element_type(&field.ty).is_some() => split_list_value(quote! { Self::fetch_param(source, #key)? }),
+                Some(key) if map_entry_types(&field.ty).is_some() => split_map_value(&field.ty, quote! { Self::fetch_param(source, #key)? }),
+                Some(key) if generic_inner_type(&field.ty, "Option").is_some_and(is_string_type) => {
+                    quote! { Self::fetch_param(source, #key)? }
+                }
+                Some(key) => quote! { Self::fetch_param(source, #key)?.and_then(|s| s.parse().ok()) },
+                None if attrs.flatten => quote! { Default::default() },
+                None => quote! { None },
+            };
+            let cfg_attrs = cfg_attrs(field);
+            quote! {
+                #(#cfg_attrs)*
+                #ident: #value
+            }
+        });
+
+        quote! {
+            pub fn from_source(source: &dyn ::config_loader_trait::ConfigSource) -> Result<Self, ::config_loader_trait::ConfigError> {
+                Ok(Self {
+                    #(#param_assignments,)*
+                    parse_fallbacks: Vec::new(),
+                    cleared: Vec::new(),
+                })
+            }
+
+            pub fn fetch_param(source: &dyn ::config_loader_trait::ConfigSource, key: &str) -> Result<Option<String>, ::config_loader_trait::ConfigError> {
+                source.fetch(key).map_err(|err| ::config_loader_trait::ConfigError::Source(format!("{key}: {err}")))
+            }
+        }
+    };
+
+    let read_config_file_function = {
+        let (read_contents, check_size) = match struct_attrs.max_file_size {
+            Some(limit) => (
+                quote! {
+                    file.take(#limit + 1).read_to_end(&mut contents)?;
+                },
+                quote! {
+                    if contents.len() as u64 > #limit {
+                        return Err(::config_loader_trait::ConfigError::TooLarge {
+                            path: config_path.display().to_string(),
+                            limit: #limit,
+                        });
+                    }
+                },
+            ),
+            None => (
+                quote! {
+                    file.read_to_end(&mut contents)?;
+                },
+                quote! {},
+            ),
+        };
+
+        // With #[load_config(verify = "sha256")] the file must match the digest in
+        // a sibling `<path>.sha256` (sha256sum format); a missing digest fails too.
+        let verify_contents = match &struct_attrs.verify {
+            Some(_) => quote! {
+                let mut digest_path = config_path.as_os_str().to_owned();
+                digest_path.push(".sha256");
+                let digest_path = std::path::PathBuf::from(digest_path);
+                let expected = ctx.read_to_string(&digest_path).map_err(|err| ::config_loader_trait::ConfigError::IntegrityCheck {
+                    path: config_path.display().to_string(),
+                    message: format!("cannot read checksum file {}: {err}", digest_path.display()),
+                })?;
+                let expected = expected.split_whitespace().next().unwrap_or_default().to_ascii_lowercase();
+                let actual = <sha2::Sha256 as sha2::Digest>::digest(&contents)
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<String>();
+                if actual != expected {
+                    return Err(::config_loader_trait::ConfigError::IntegrityCheck {
+                        path: config_path.display().to_string(),
+                        message: format!("expected sha256 {expected}, got {actual}"),
+                    });
+                }
+            },
+            None => quote! {},
+        };
+
+        // Called once the file is known to exist, so failing to open or read
+        // it is an error rather than a missing layer, as is non-UTF-8 content.
+        quote! {
+            pub fn read_config_file(ctx: &::config_loader_trait::LoadContext, config_path: &std::path::Path) -> Result<String, ::config_loader_trait::ConfigError> {
+                use std::io::Read;
+                let contents = match &ctx.read_file {
+                    Some(read_file) => read_file(config_path)?.into_bytes(),
+                    None => {
+                        let mut file = std::fs::File::open(config_path)?;
+                        let mut contents = Vec::new();
+                        #read_contents
+                        contents
+                    }
+                };
+                #check_size
+                #verify_contents
+                String::from_utf8(contents).map_err(|err| ::config_loader_trait::ConfigError::parse(config_path, err))
+            }
+        }
+    };
+
+    let warn_deprecated_keys_function = {
+        let file_aliases = fields.named.iter().zip(&field_attrs).flat_map(|(field, attrs)| {
+            let canonical = struct_attrs.file_key(field);
+            let cfg_attrs = cfg_attrs(field);
+            attrs.file_aliases.iter().map(move |alias| quote! { #(#cfg_attrs)* (#alias, #canonical) })
+        });
+
+        quote! {
+            pub fn warn_deprecated_keys(value: &serde_yaml::Value) {
+                const FILE_ALIASES: &[(&str, &str)] = &[#(#file_aliases),*];
+                if let Some(mapping) = value.as_mapping() {
+                    for (alias, canonical) in FILE_ALIASES {
+                        if mapping.contains_key(*alias) {
+                            eprintln!("warning: config key `{alias}` is deprecated, use `{canonical}` instead");
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    // With #[load_config(template = "minijinja")] the raw file is rendered
+    // with `env` (the context's env map) and `now` (unix seconds) in scope.
+    let render_template_function = match &struct_attrs.template {
+        Some(_) => quote! {
+            pub fn render_template(ctx: &::config_loader_trait::LoadContext, contents: &str) -> Result<String, ::config_loader_trait::ConfigError> {
+                let now = ctx.now.duration_since(std::time::UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or_default();
+                let environment = minijinja::Environment::new();
+                environment
+                    .render_str(contents, minijinja::context! { env => &ctx.env, now => now })
+                    .map_err(|err| ::config_loader_trait::ConfigError::Invalid(format!("template error: {err}")))
+            }
+        },
+        None => quote! {
+            pub fn render_template(ctx: &::config_loader_trait::LoadContext, contents: &str) -> Result<String, ::config_loader_trait::ConfigError> {
+                Ok(contents.to_string())
+            }
+        },
+    };
+
+    // Walks the document with a visitor that sees every mapping entry, since
+    // parsing to serde_yaml::Value collapses the error into a generic one.
+    let duplicate_key_function = quote! {
+        pub fn find_duplicate_key(contents: &str) -> Option<String> {
+            struct KeyCheck(Option<String>);
+
+            struct KeyCheckVisitor;
+
+            impl<'de> serde::Deserialize<'de> for KeyCheck {
+                fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    deserializer.deserialize_any(KeyCheckVisitor)
+                }
+            }
+
+            impl<'de> serde::de::Visitor<'de> for KeyCheckVisitor {
+                type Value = KeyCheck;
+
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("any YAML value")
+                }
+
+                fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<KeyCheck, A::Error> {
+                    let mut seen = std::collections::HashSet::new();
+                    let mut duplicate = None;
+                    while let Some(key) = map.next_key::<serde_yaml::Value>()? {
+                        let nested = map.next_value::<KeyCheck>()?;
+                        let name = match &key {
+                            serde_yaml::Value::String(name) => name.clone(),
+                            other => serde_yaml::to_string(other).unwrap_or_default().trim_end().to_string(),
+                        };
+                        let repeated = !seen.insert(key);
+                        if duplicate.is_none() {
+                            duplicate = if repeated { Some(name) } else { nested.0.map(|nested| format!("{name}.{nested}")) };
+                        }
+                    }
+                    Ok(KeyCheck(duplicate))
+                }
+
+                fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<KeyCheck, A::Error> {
+                    let mut duplicate = None;
+                    let mut index = 0;
+                    while let Some(nested) = seq.next_element::<KeyCheck>()? {
+                        if duplicate.is_none() {
+                            duplicate = nested.0.map(|nested| format!("{index}.{nested}"));
+                        }
+                        index += 1;
+                    }
+                    Ok(KeyCheck(duplicate))
+                }
+
+                fn visit_enum<A: serde::de::EnumAccess<'de>>(self, data: A) -> Result<KeyCheck, A::Error> {
+                    let (_, variant) = data.variant::<serde_yaml::Value>()?;
+                    serde::de::VariantAccess::newtype_variant::<KeyCheck>(variant)
+                }
+
+                fn visit_some<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<KeyCheck, D::Error> {
+                    <KeyCheck as serde::Deserialize>::deserialize(deserializer)
+                }
+
+                fn visit_bool<E>(self, _: bool) -> Result<KeyCheck, E> {
+                    Ok(KeyCheck(None))
+                }
+
+                fn visit_i64<E>(self, _: i64) -> Result<KeyCheck, E> {
+                    Ok(KeyCheck(None))
+                }
+
+                fn visit_u64<E>(self, _: u64) -> Result<KeyCheck, E> {
+                    Ok(KeyCheck(None))
+                }
+
+                fn visit_f64<E>(self, _: f64) -> Result<KeyCheck, E> {
+                    Ok(KeyCheck(None))
+                }
+
+                fn visit_str<E>(self, _: &str) -> Result<KeyCheck, E> {
+                    Ok(KeyCheck(None))
+                }
+
+                fn visit_unit<E>(self) -> Result<KeyCheck, E> {
+                    Ok(KeyCheck(None))
+                }
+
+                fn visit_none<E>(self) -> Result<KeyCheck, E> {
+                    Ok(KeyCheck(None))
+                }
+            }
+
+            serde_yaml::from_str::<KeyCheck>(contents).ok()?.0
+        }
+    };
+
+    // An explicit `~` in a file is kept apart from a missing key, so a later
+    // config file can reset a field an earlier one set: the field then falls
+    // back to its clap default (or Default) instead of the earlier value.
+    let cleared_checks = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+        let name = &field.ident;
+        let name_str = name.as_ref().unwrap().to_string();
+        let key = struct_attrs.file_key(field);
+        let file_aliases = &attrs.file_aliases;
+        let cfg_attrs = cfg_attrs(field);
+        if attrs.flatten {
+            return quote! {
+                #(#cfg_attrs)*
+                if let Some(section) = value.get(#key).or(section_cleared.then_some(value)) {
+                    self.#name.mark_cleared(section);
+                }
+            };
+        }
+        quote! {
+            #(#cfg_attrs)*
+            if section_cleared || [#key, #(#file_aliases),*].iter().any(|key| value.get(key).is_some_and(serde_yaml::Value::is_null)) {
+                self.cleared.push(#name_str);
+            }
+        }
+    });
+
+    // Kept free of any file or process access so it can be driven directly,
+    // e.g. by the fuzz target under fuzz/.
+    let from_yaml_str_function = quote! {
+        pub fn from_yaml_str(contents: &str) -> Result<Self, ::config_loader_trait::ConfigError> {
+            if let Some(key) = Self::find_duplicate_key(contents) {
+                return Err(::config_loader_trait::ConfigError::DuplicateKey { key, path: String::new() });
+            }
+            let value = serde_yaml::from_str(contents).map_err(|err| ::config_loader_trait::ConfigError::parse(std::path::Path::new(""), err))?;
+            Self::from_yaml_value(value)
+        }
+
+        pub fn from_yaml_value(value: serde_yaml::Value) -> Result<Self, ::config_loader_trait::ConfigError> {
+            Self::warn_deprecated_keys(&value);
+            let mut opts: Self =
+                serde::Deserialize::deserialize(&value).map_err(|err| ::config_loader_trait::ConfigError::parse(std::path::Path::new(""), err))?;
+            opts.mark_cleared(&value);
+            Ok(opts)
+        }
+
+        // A `~` section clears every field under it.
+        pub fn mark_cleared(&mut self, value: &serde_yaml::Value) {
+            let section_cleared = value.is_null();
+            #(#cleared_checks)*
+        }
+    };
+
+    // With #[load_config(expand_strings)] `${VAR}` references in string-typed
+    // file values are replaced from the context env after deserializing, so
+    // numeric/bool fields are never touched. An undefined variable is left as
+    // written, or is an error with expand_strings = "error".
+    let expand_strings_function = match struct_attrs.expand_strings {
+        Some(undefined_vars) => {
+            let field_expansions = fields.named.iter().filter_map(|field| {
+                let name = &field.ident;
+                let ty = generic_inner_type(&field.ty, "Option").unwrap_or(&field.ty);
+                let cfg_attrs = cfg_attrs(field);
+                let expansion = if is_string_type(ty) {
+                    quote! {
+                        if let Some(value) = &mut self.#name {
+                            *value = Self::expand_env_vars(ctx, value)?;
+                        }
+                    }
+                } else if generic_inner_type(ty, "Vec").is_some_and(is_string_type) {
+                    quote! {
+                        if let Some(values) = &mut self.#name {
+                            for value in values.iter_mut() {
+                                *value = Self::expand_env_vars(ctx, value)?;
+                            }
+                        }
+                    }
+                } else {
+                    return None;
+                };
+                Some(quote! {
+                    #(#cfg_attrs)*
+                    #expansion
+                })
+            });
+            // `${field}` is left for the field_refs pass rather than treated as an
+            // undefined env var.
+            let field_names = fields.named.iter().map(|field| field.ident.as_ref().unwrap().to_string());
+            let field_ref = if struct_attrs.field_refs {
+                quote! { None if [#(#field_names),*].contains(&name) => expanded.push_str(&rest[..end + 1]), }
+            } else {
+                quote! {}
+            };
+            let undefined = if undefined_vars == UndefinedVars::Error {
+                quote! { #field_ref None => return Err(::config_loader_trait::ConfigError::Invalid(format!("undefined variable `{name}` in config value `{value}`"))), }
+            } else {
+                quote! { None => expanded.push_str(&rest[..end + 1]), }
+            };
+
+            quote! {
+                pub fn expand_strings(&mut self, ctx: &::config_loader_trait::LoadContext) -> Result<(), ::config_loader_trait::ConfigError> {
+                    #(#field_expansions)*
+                    Ok(())
+                }
+
+                pub fn expand_env_vars(ctx: &::config_loader_trait::LoadContext, value: &str) -> Result<String, ::config_loader_trait::ConfigError> {
+                    let mut expanded = String::new();
+                    let mut rest = value;
+                    while let Some(start) = rest.find("${") {
+                        expanded.push_str(&rest[..start]);
+                        rest = &rest[start..];
+                        let Some(end) = rest.find('}') else {
+                            break;
+                        };
+                        let name = &rest[2..end];
+                        match ctx.env.get(name) {
+                            Some(var) => expanded.push_str(var),
+                            #undefined
+                        }
+                        rest = &rest[end + 1..];
+                    }
+                    expanded.push_str(rest);
+                    Ok(expanded)
+                }
+            }
+        }
+        None => quote! {
+            pub fn expand_strings(&mut self, ctx: &::config_loader_trait::LoadContext) -> Result<(), ::config_loader_trait::ConfigError> {
+                Ok(())
+            }
+        },
+    };
+
+    // With warn_on_parse_error a file value of the wrong type is reported and
+    // dropped before the file is deserialized, so the field falls back to the
+    // lower layers instead of failing the load.
+    let file_parse_checks = fields.named.iter().zip(&field_attrs).filter(|(_, attrs)| attrs.warn_on_parse_error).map(|(field, _)| {
+        let name_str = field.ident.as_ref().unwrap().to_string();
+        let key = struct_attrs.file_key(field);
+        let ty = &field.ty;
+        let option_ty = if is_option_type(ty) {
+            quote! { #ty }
+        } else {
+            quote! { Option<#ty> }
+        };
+        let cfg_attrs = cfg_attrs(field);
+        quote! {
+            #(#cfg_attrs)*
+            {
+                let key = serde_yaml::Value::from(#key);
+                if let Some(Err(err)) = mapping.get(&key).map(|value| serde_yaml::from_value::<#option_ty>(value.clone())) {
+                    eprintln!("warning: invalid `{}` in config file {}: {err}, ignoring it", #key, config_path.display());
+                    mapping.remove(&key);
+                    parse_fallbacks.push((#name_str, ::config_loader_trait::Layer::File));
+                }
+            }
+        }
+    });
+
+    let load_yaml_function = quote! {
+        pub fn read_yaml_value(ctx: &::config_loader_trait::LoadContext, config_path: Option<&std::path::Path>) -> Result<Option<serde_yaml::Value>, ::config_loader_trait::ConfigError> {
+            if let Some(config_path) = config_path {
+                if ctx.file_exists(config_path) {
+                    let config_contents = Self::read_config_file(ctx, config_path)?;
+                    let config_contents = Self::render_template(ctx, &config_contents)?;
+                    let value = match ::config_loader_trait::ConfigFormat::from_path(config_path) {
+                        ::config_loader_trait::ConfigFormat::Toml => {
+                            let value: toml::Value = toml::from_str(&config_contents).map_err(|err| ::config_loader_trait::ConfigError::parse(config_path, err))?;
+                            serde_yaml::to_value(value).map_err(|err| ::config_loader_trait::ConfigError::parse(config_path, err))?
+                        }
+                        ::config_loader_trait::ConfigFormat::Json => {
+                            let value: serde_json::Value = serde_json::from_str(&config_contents).map_err(|err| ::config_loader_trait::ConfigError::parse(config_path, err))?;
+                            serde_yaml::to_value(value).map_err(|err| ::config_loader_trait::ConfigError::parse(config_path, err))?
+                        }
+                        ::config_loader_trait::ConfigFormat::Yaml => {
+                            if let Some(key) = Self::find_duplicate_key(&config_contents) {
+                                return Err(::config_loader_trait::ConfigError::DuplicateKey {
+                                    key,
+                                    path: config_path.display().to_string(),
+                                });
+                            }
+                            serde_yaml::from_str(&config_contents).map_err(|err| ::config_loader_trait::ConfigError::parse(config_path, err))?
+                        }
+                    };
+                    // An empty file holds no settings rather than a null config.
+                    return Ok(Some(value).filter(|value| !value.is_null()));
+                }
+            }
+            Ok(None)
+        }
+
+        // Mappings merge key by key, anything else in the overlay replaces the base.
+        pub fn merge_yaml_values(base: serde_yaml::Value, overlay: serde_yaml::Value) -> serde_yaml::Value {
+            match (base, overlay) {
+                (serde_yaml::Value::Mapping(mut base), serde_yaml::Value::Mapping(overlay)) => {
+                    for (key, value) in overlay {
+                        match base.get_mut(&key) {
+                            Some(existing) => *existing = Self::merge_yaml_values(std::mem::take(existing), value),
+                            None => {
+                                base.insert(key, value);
+                            }
+                        }
+                    }
+                    serde_yaml::Value::Mapping(base)
+                }
+                (_, overlay) => overlay,
+            }
+        }
+
+        // With a profile only that top-level section of the file is loaded; a
+        // profile the file lacks leaves the file layer empty.
+        pub fn load_yaml(ctx: &::config_loader_trait::LoadContext, config_path: Option<&std::path::Path>, profile: Option<&str>) -> Result<Self, ::config_loader_trait::ConfigError> {
+            let value = match (Self::read_yaml_value(ctx, config_path)?, profile) {
+                (Some(serde_yaml::Value::Mapping(mut sections)), Some(profile)) => sections.remove(profile),
+                (_, Some(_)) => None,
+                (value, None) => value,
+            };
+            let mut parse_fallbacks = Vec::new();
+            let mut yml_opts = match (config_path, value) {
+                (Some(config_path), Some(mut value)) => {
+                    if let Some(mapping) = value.as_mapping_mut() {
+                        #(#file_parse_checks)*
+                    }
+                    Self::from_yaml_value(value).map_err(|err| match err {
+                        ::config_loader_trait::ConfigError::Parse { source, .. } => ::config_loader_trait::ConfigError::parse(config_path, source),
+                        other => other,
+                    })?
+                }
+                _ => Self::default(),
+            };
+            yml_opts.parse_fallbacks = parse_fallbacks;
+            yml_opts.expand_strings(ctx)?;
+            Ok(yml_opts)
+        }
+
+        // The profiles a file defines: its top-level keys whose values are
+        // sections, so the plain settings of a file without profiles are not
+        // taken for ones. A file that is empty or not a mapping defines none.
+        pub fn list_profiles(ctx: &::config_loader_trait::LoadContext, config_path: &std::path::Path) -> Result<Vec<String>, ::config_loader_trait::ConfigError> {
+            let sections = match Self::read_yaml_value(ctx, Some(config_path))? {
+                Some(serde_yaml::Value::Mapping(sections)) => sections,
+                _ => return Ok(Vec::new()),
+            };
+            Ok(sections
+                .iter()
+                .filter(|(_, section)| section.is_mapping())
+                .filter_map(|(key, _)| key.as_str().map(str::to_string))
+                .collect())
+        }
+    };
+
+    let default_config_function = {
+        let field_entries = fields.named.iter().filter(|field| !is_config_field(field)).map(|field| {
+            let name = &field.ident;
+            let key = struct_attrs.file_key(field);
+            let doc_lines = doc_lines(field);
+            let cfg_attrs = cfg_attrs(field);
+            let entry = match flattened(field) {
+                Some(opts_ty) => quote! {
+                    contents.push_str(concat!(#key, ":\n"));
+                    for line in #opts_ty::default_config_yaml().map_err(::config_loader_trait::ConfigError::serialize)?.lines() {
+                        contents.push_str("  ");
+                        contents.push_str(line);
+                        contents.push('\n');
+                    }
+                },
+                None => quote! {
+                    match &default_value_opts.#name {
+                        Some(value) => contents.push_str(&serde_yaml::to_string(&std::collections::BTreeMap::from([(#key, value)])).map_err(::config_loader_trait::ConfigError::serialize)?),
+                        None => contents.push_str(concat!("# ", #key, ":\n")),
+                    }
+                },
+            };
+            quote! {
+                #(#cfg_attrs)*
+                {
+                    #(
+                        contents.push('#');
+                        contents.push_str(#doc_lines);
+                        contents.push('\n');
+                    )*
+                    #entry
+                }
+            }
+        });
+
+        quote! {
+            pub fn default_config_yaml() -> Result<String, ::config_loader_trait::ConfigError> {
+                let default_value_opts = Self::parse_from([] as [&str; 0]);
+                let mut contents = String::new();
+                #(#field_entries)*
+                Ok(contents)
+            }
+        }
+    };
+
+    let file_rename_rule = struct_attrs.file_case.serde_rule();
+    // Secret fields are left out of every rendering meant to be read or
+    // written back: the minimal, canonical and dumped config.
+    let minimal_yaml_function = {
+        let field_entries = fields.named.iter().zip(&field_attrs).filter(|(field, attrs)| !is_config_field(field) && !attrs.secret).map(|(field, _)| {
+            let name = &field.ident;
+            let key = struct_attrs.file_key(field);
+            let cfg_attrs = cfg_attrs(field);
+            if flattened(field).is_some() {
+                return quote! {
+                    #(#cfg_attrs)*
+                    if let Some(value) = Self::nested_section(self.#name.to_minimal_yaml())? {
+                        mapping.insert(#key.into(), value);
+                    }
+                };
+            }
+            quote! {
+                #(#cfg_attrs)*
+                if let Some(value) = &self.#name {
+                    if self.#name.as_ref() != default_value_opts.#name.as_ref() {
+                        mapping.insert(#key.into(), serde_yaml::to_value(value).map_err(::config_loader_trait::ConfigError::serialize)?);
+                    }
+                }
+            }
+        });
+
+        quote! {
+            pub fn to_minimal_yaml(&self) -> Result<String, ::config_loader_trait::ConfigError> {
+                let default_value_opts = Self::parse_from([] as [&str; 0]);
+                let mut mapping = serde_yaml::Mapping::new();
+                #(#field_entries)*
+                serde_yaml::to_string(&mapping).map_err(::config_loader_trait::ConfigError::serialize)
+            }
+        }
+    };
+
+    // With #[load_config(field_refs)] `${field_name}` in a string field is
+    // replaced by that field's resolved value, after all layers are applied.
+    // References are resolved depth-first so chains work, a cycle is an error,
+    // and names that aren't fields (or are unset) are left as written.
+    let field_refs_function = if struct_attrs.field_refs {
+        let render_fields = fields.named.iter().filter(|field| flattened(field).is_none()).map(|field| {
+            let name = &field.ident;
+            let key = name.as_ref().unwrap().to_string();
+            let cfg_attrs = cfg_attrs(field);
+            quote! {
+                #(#cfg_attrs)*
+                if let Some(value) = &self.#name {
+                    let value = match serde_yaml::to_value(value).map_err(::config_loader_trait::ConfigError::serialize)? {
+                        serde_yaml::Value::String(value) => value,
+                        other => serde_yaml::to_string(&other).map_err(::config_loader_trait::ConfigError::serialize)?.trim_end().to_string(),
+                    };
+                    raw.insert(#key, value);
+                }
+            }
+        });
+        let string_fields = fields
+            .named
+            .iter()
+            .filter(|field| is_string_type(generic_inner_type(&field.ty, "Option").unwrap_or(&field.ty)))
+            .collect::<Vec<_>>();
+        let string_field_names = string_fields.iter().map(|field| field.ident.as_ref().unwrap().to_string());
+        let write_back = string_fields.iter().map(|field| {
+            let name = &field.ident;
+            let key = name.as_ref().unwrap().to_string();
+            let cfg_attrs = cfg_attrs(field);
+            quote! {
+                #(#cfg_attrs)*
+                if let Some(value) = resolved.remove(#key) {
+                    self.#name = Some(value);
+                }
+            }
+        });
+
+        quote! {
+            pub fn resolve_field_refs(&mut self) -> Result<(), ::config_loader_trait::ConfigError> {
+                let mut raw = std::collections::BTreeMap::<&'static str, String>::new();
+                #(#render_fields)*
+                let string_fields: &[&'static str] = &[#(#string_field_names),*];
+                let mut resolved = std::collections::BTreeMap::new();
+                for name in string_fields.iter().copied().filter(|name| raw.contains_key(name)) {
+                    Self::resolve_field_ref(name, string_fields, &raw, &mut resolved, &mut Vec::new())?;
+                }
+                #(#write_back)*
+                Ok(())
+            }
+
+            pub fn resolve_field_ref(
+                name: &'static str,
+                string_fields: &[&'static str],
+                raw: &std::collections::BTreeMap<&'static str, String>,
+                resolved: &mut std::collections::BTreeMap<&'static str, String>,
+                stack: &mut Vec<&'static str>,
+            ) -> Result<String, ::config_loader_trait::ConfigError> {
+                if let Some(value) = resolved.get(name) {
+                    return Ok(value.clone());
+                }
+                if !string_fields.contains(&name) {
+                    return Ok(raw[name].clone());
+                }
+                if let Some(cycle_start) = stack.iter().position(|entry| *entry == name) {
+                    stack.push(name);
+                    return Err(::config_loader_trait::ConfigError::CyclicReference(stack[cycle_start..].to_vec()));
+                }
+                stack.push(name);
+                let mut expanded = String::new();
+                let mut rest = raw[name].as_str();
+                while let Some(start) = rest.find("${") {
+                    expanded.push_str(&rest[..start]);
+                    rest = &rest[start..];
+                    let Some(end) = rest.find('}') else {
+                        break;
+                    };
+                    match raw.get_key_value(&rest[2..end]) {
+                        Some((reference, _)) => {
+                            expanded.push_str(&Self::resolve_field_ref(reference, string_fields, raw, resolved, stack)?)
+                        }
+                        None => expanded.push_str(&rest[..end + 1]),
+                    }
+                    rest = &rest[end + 1..];
+                }
+                expanded.push_str(rest);
+                stack.pop();
+                resolved.insert(name, expanded.clone());
+                Ok(expanded)
+            }
+        }
+    } else {
+        quote! {
+            pub fn resolve_field_refs(&mut self) -> Result<(), ::config_loader_trait::ConfigError> {
+                Ok(())
+            }
+        }
+    };
+
+    // Top-level keys in field declaration order; nested mappings (e.g. from a
+    // HashMap) sorted by key so their iteration order can't leak into diffs.
+    let canonical_yaml_function = {
+        let field_entries = fields.named.iter().zip(&field_attrs).filter(|(field, attrs)| !is_config_field(field) && !attrs.secret).map(|(field, _)| {
+            let name = &field.ident;
+            let key = struct_attrs.file_key(field);
+            let cfg_attrs = cfg_attrs(field);
+            if flattened(field).is_some() {
+                return quote! {
+                    #(#cfg_attrs)*
+                    if let Some(value) = Self::nested_section(self.#name.to_canonical_yaml())? {
+                        mapping.insert(#key.into(), value);
+                    }
+                };
+            }
+            quote! {
+                #(#cfg_attrs)*
+                if let Some(value) = &self.#name {
+                    mapping.insert(#key.into(), Self::sort_yaml_mappings(serde_yaml::to_value(value).map_err(::config_loader_trait::ConfigError::serialize)?));
+                }
+            }
+        });
+
+        let dump_entries = fields.named.iter().zip(&field_attrs).filter(|(field, attrs)| !is_config_field(field) && !attrs.secret).map(|(field, _)| {
+            let name = &field.ident;
+            let key = struct_attrs.file_key(field);
+            let cfg_attrs = cfg_attrs(field);
+            if flattened(field).is_some() {
+                return quote! {
+                    #(#cfg_attrs)*
+                    if let Some(value) = Self::nested_section(self.#name.to_canonical_yaml())? {
+                        mapping.insert(#key.into(), value);
+                    }
+                };
+            }
+            quote! {
+                #(#cfg_attrs)*
+                if let Some(value) = &self.#name {
+                    mapping.insert(#key.into(), serde_yaml::to_value(value).map_err(::config_loader_trait::ConfigError::serialize)?);
+                }
+            }
+        });
+
+        quote! {
+            pub fn to_canonical_yaml(&self) -> Result<String, ::config_loader_trait::ConfigError> {
+                let mut mapping = serde_yaml::Mapping::new();
+                #(#field_entries)*
+                serde_yaml::to_string(&mapping).map_err(::config_loader_trait::ConfigError::serialize)
+            }
+
+            // Every set field under its file key, for writing the resolved config
+            // back out in any of the file formats.
+            pub fn dump(&self, format: ::config_loader_trait::ConfigFormat) -> Result<String, ::config_loader_trait::ConfigError> {
+                let mut mapping = serde_yaml::Mapping::new();
+                #(#dump_entries)*
+                match format {
+                    ::config_loader_trait::ConfigFormat::Yaml => serde_yaml::to_string(&mapping).map_err(::config_loader_trait::ConfigError::serialize),
+                    ::config_loader_trait::ConfigFormat::Toml => toml::to_string_pretty(&mapping).map_err(::config_loader_trait::ConfigError::serialize),
+                    ::config_loader_trait::ConfigFormat::Json => serde_json::to_string_pretty(&mapping).map_err(::config_loader_trait::ConfigError::serialize),
+                }
+            }
+
+            // A flattened field's section, read back from the nested opts' own
+            // rendering; None when it sets nothing.
+            pub fn nested_section<E>(yaml: Result<String, E>) -> Result<Option<serde_yaml::Value>, ::config_loader_trait::ConfigError>
+            where
+                E: std::error::Error + Send + Sync + 'static,
+            {
+                let value: serde_yaml::Value = serde_yaml::from_str(&yaml.map_err(::config_loader_trait::ConfigError::serialize)?).map_err(::config_loader_trait::ConfigError::serialize)?;
+                Ok(Some(value).filter(|value| value.as_mapping().is_some_and(|mapping| !mapping.is_empty())))
+            }
+
+            pub fn sort_yaml_mappings(value: serde_yaml::Value) -> serde_yaml::Value {
+                match value {
+                    serde_yaml::Value::Mapping(mapping) => {
+                        let mut entries = mapping
+                            .into_iter()
+                            .map(|(key, value)| (serde_yaml::to_string(&key).unwrap_or_default(), key, Self::sort_yaml_mappings(value)))
+                            .collect::<Vec<_>>();
+                        entries.sort_by(|(lhs, ..), (rhs, ..)| lhs.cmp(rhs));
+                        serde_yaml::Value::Mapping(entries.into_iter().map(|(_, key, value)| (key, value)).collect())
+                    }
+                    serde_yaml::Value::Sequence(values) => {
+                        serde_yaml::Value::Sequence(values.into_iter().map(Self::sort_yaml_mappings).collect())
+                    }
+                    serde_yaml::Value::Tagged(tagged) => serde_yaml::Value::Tagged(Box::new(serde_yaml::value::TaggedValue {
+                        tag: tagged.tag,
+                        value: Self::sort_yaml_mappings(tagged.value),
+                    })),
+                    other => other,
+                }
+            }
+        }
+    };
+
+    // FNV-1a over the YAML form of every field in declaration order, so the
+    // hash is stable across runs, platforms and Rust versions (unlike
+    // DefaultHasher). Nested mappings are sorted by key first, so a HashMap
+    // field hashes the same whatever its iteration order.
+    let config_hash_function = {
+        let field_entries = fields.named.iter().map(|field| {
+            let name = &field.ident;
+            let key = name.as_ref().unwrap().to_string();
+            let cfg_attrs = cfg_attrs(field);
+            // The nested opts hash their own fields, secrets included.
+            let value = match flattened(field) {
+                Some(_) => quote! { self.#name.config_hash().into() },
+                None => quote! { Self::sort_yaml_mappings(serde_yaml::to_value(&self.#name).unwrap_or_default()) },
+            };
+            quote! {
+                #(#cfg_attrs)*
+                mapping.insert(#key.into(), #value);
+            }
+        });
+
+        quote! {
+            pub fn config_hash(&self) -> u64 {
+                let mut mapping = serde_yaml::Mapping::new();
+                #(#field_entries)*
+                serde_yaml::to_string(&mapping)
+                    .unwrap_or_default()
+                    .bytes()
+                    .fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+            }
+        }
+    };
+
+    let describe_function = {
+        let field_descriptions = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+            let cfg_attrs = cfg_attrs(field);
+            if let Some(opts_ty) = flattened(field) {
+                let file_key = struct_attrs.file_key(field);
+                let env = struct_attrs.env_name(field, attrs);
+                return quote! {
+                    #(#cfg_attrs)*
+                    fields.extend(#opts_ty::describe_with_prefix(&format!("{}{}__", prefix, #env)).fields.into_iter().map(|mut field| {
+                        field.file_key = format!("{}.{}", #file_key, field.file_key);
+                        field
+                    }));
+                };
+            }
+            let name = field.ident.as_ref().unwrap().to_string();
+            let arg_id = clap_arg_id(field);
+            let required = attrs.required;
+            let secret = attrs.secret;
+            let ty = type_name(&field.ty);
+            let doc = doc_lines(field).iter().map(|line| line.value().trim().to_string()).collect::<Vec<_>>().join(" ");
+            let doc = if doc.is_empty() { quote! { None } } else { quote! { Some(#doc) } };
+            let env = struct_attrs.env_name(field, attrs);
+            let env = if !struct_attrs.env_enabled(&env) {
+                quote! { None }
+            } else if attrs.env.is_some() {
+                quote! { Some(#env.to_string()) }
+            } else {
+                quote! { Some(format!("{}{}", prefix, #env)) }
+            };
+            let file_key = struct_attrs.file_key(field);
+            let env_help = match &attrs.env_help {
+                Some(help) => quote! { Some(#help) },
+                None => quote! { None },
+            };
+            let param = match &attrs.from_param {
+                Some(key) => quote! { Some(#key) },
+                None => quote! { None },
+            };
+            quote! {
+                #(#cfg_attrs)*
+                fields.push(::config_loader_trait::FieldDescription {
+                    name: #name,
+                    ty: #ty,
+                    doc: #doc,
+                    env: #env,
+                    env_help: #env_help,
+                    file_key: #file_key.to_string(),
+                    param: #param,
+                    default: default_value(#arg_id),
+                    required: #required,
+                    secret: #secret,
+                });
+            }
+        });
+        let struct_name_str = struct_name.to_string();
+
+        quote! {
+            pub fn describe() -> ::config_loader_trait::ConfigDescription {
+                Self::describe_with_prefix(#env_prefix)
+            }
+
+            pub fn describe_with_prefix(prefix: &str) -> ::config_loader_trait::ConfigDescription {
+                let command = <Self as clap::CommandFactory>::command();
+                let default_value = |id: &str| {
+                    let arg = command.get_arguments().find(|arg| arg.get_id() == id)?;
+                    let values = arg.get_default_values();
+                    if values.is_empty() {
+                        return None;
+                    }
+                    Some(values.iter().map(|value| value.to_string_lossy()).collect::<Vec<_>>().join(","))
+                };
+                let mut fields = Vec::new();
+                #(#field_descriptions)*
+                ::config_loader_trait::ConfigDescription {
+                    name: #struct_name_str,
+                    fields,
+                }
+            }
+        }
+    };
+
+    // With #[load_config(dynamic_values_from = "allowed_levels")] the field only
+    // accepts, on the command line, the values the named `Vec<String>` field
+    // resolved to. The args are parsed once to find and load the config, then
+    // a second time with those values as the arg's only valid ones.
+    let dynamic_values_function = {
+        let mut checks = Vec::new();
+        for (field, attrs) in fields.named.iter().zip(&field_attrs) {
+            let Some(values_from) = &attrs.dynamic_values_from else {
+                continue;
+            };
+            let values_field = fields.named.iter().find(|candidate| candidate.ident.as_ref().is_some_and(|ident| ident == &values_from.value()));
+            let Some(values_field) = values_field else {
+                return syn::Error::new(values_from.span(), format!("no field named `{}`", values_from.value())).to_compile_error();
+            };
+            if !vec_element_type(&values_field.ty).is_some_and(is_string_type) {
+                return syn::Error::new(values_from.span(), "dynamic_values_from must name a Vec<String> field").to_compile_error();
+            }
+            let values_name = &values_field.ident;
+            let id = clap_arg_id(field);
+            let cfg_attrs = cfg_attrs(field);
+            checks.push(quote! {
+                #(#cfg_attrs)*
+                // mut_arg panics on an id that is not a registered arg.
+                let values = self.#values_name.clone().filter(|_| command.get_arguments().any(|arg| arg.get_id() == #id));
+                if let Some(values) = values {
+                    command = command.mut_arg(#id, |arg| {
+                        arg.value_parser(move |value: &str| {
+                            if values.iter().any(|allowed| allowed == value) {
+                                Ok(value.to_string())
+                            } else {
+                                Err(format!("expected one of: {}", values.join(", ")))
+                            }
+                        })
+                    });
+                }
+            });
+        }
+        if checks.is_empty() {
+            quote! {
+                pub fn check_dynamic_values(&self, args: &[String]) -> Result<(), ::config_loader_trait::ConfigError> {
+                    Ok(())
+                }
+            }
+        } else {
+            quote! {
+                pub fn check_dynamic_values(&self, args: &[String]) -> Result<(), ::config_loader_trait::ConfigError> {
+                    let mut command = <Self as clap::CommandFactory>::command();
+                    #(#checks)*
+                    command.try_get_matches_from(args)?;
+                    Ok(())
+                }
+            }
+        }
+    };
+
+    // Written out rather than derived so a secret field shows only whether it
+    // is set.
+    let opts_debug_impl = {
+        let debug_fields = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
+            let name = &field.ident;
+            let name_str = name.as_ref().unwrap().to_string();
+            let cfg_attrs = cfg_attrs(field);
+            let value = if attrs.secret {
+                quote! { &self.#name.as_ref().map(|_| "***") }
+            } else {
+                quote! { &self.#name }
+            };
+            quote! {
+                #(#cfg_attrs)*
+                debug.field(#name_str, #value);
+            }
+        });
+        let opts_name_str = config_loader_opts_ident.to_string();
+        quote! {
+            impl std::fmt::Debug for #config_loader_opts_ident {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    let mut debug = f.debug_struct(#opts_name_str);
+                    #(#debug_fields)*
+                    debug.finish()
                 }
-            } else {
-                default_value_opts.clone()
             }
         }
     };
 
     let config_loader_opts_impl = quote! {
-        #[derive(Clone, Debug, Default, serde::Deserialize, clap::Parser)]
-        #[serde(rename_all = "kebab-case")]
-        struct #config_loader_opts_ident {
+        #[derive(Clone, Default, serde::Deserialize, clap::Parser)]
+        #[serde(rename_all = #file_rename_rule)]
+        #struct_vis struct #config_loader_opts_ident {
             #(#config_loader_opts_fields)*
+            // The warn_on_parse_error fields whose value failed to parse in this
+            // layer (Env or File), for value_sources to report.
+            #[arg(skip)]
+            #[serde(skip)]
+            parse_fallbacks: Vec<(&'static str, ::config_loader_trait::Layer)>,
+            // The fields this file layer sets to `~`, which merge clears
+            // rather than inheriting from the layer below.
+            #[arg(skip)]
+            #[serde(skip)]
+            cleared: Vec<&'static str>,
         }
 
         impl #config_loader_opts_ident {
             #merge_function
             #resolve_function
+            #dynamic_values_function
+            #value_sources_function
             #from_env_function
+            #from_source_function
+            #read_config_file_function
+            #warn_deprecated_keys_function
+            #render_template_function
+            #duplicate_key_function
+            #from_yaml_str_function
+            #expand_strings_function
             #load_yaml_function
+            #default_config_function
+            #minimal_yaml_function
+            #field_refs_function
+            #canonical_yaml_function
+            #config_hash_function
+            #describe_function
         }
+
+        #opts_debug_impl
     };
 
-    let from_impl_fields = fields.named.iter().map(|field| {
+    let from_impl_fields = fields.named.iter().zip(&field_attrs).map(|(field, attrs)| {
         let name = &field.ident;
+        let cfg_attrs = cfg_attrs(field);
+        if attrs.flatten {
+            let name_str = name.as_ref().unwrap().to_string();
+            let nested = quote! {
+                config_opts.#name.into_config().map_err(|err| ::config_loader_trait::ConfigError::Nested {
+                    field: #name_str,
+                    source: Box::new(err),
+                })?
+            };
+            // resolve clears an absent optional block, so empty means None.
+            if is_option_type(&field.ty) {
+                quote! {
+                    #(#cfg_attrs)*
+                    #name: if config_opts.#name.is_empty() { None } else { Some(#nested) }
+                }
+            } else {
+                quote! {
+                    #(#cfg_attrs)*
+                    #name: #nested
+                }
+            }
+        } else if attrs.required {
+            let name_str = name.as_ref().unwrap().to_string();
+            quote! {
+                #(#cfg_attrs)*
+                #name: config_opts.#name.take().ok_or(::config_loader_trait::ConfigError::MissingRequired(#name_str))?
+            }
+        } else if is_option_type(&field.ty) {
+            quote! {
+                #(#cfg_attrs)*
+                #name: config_opts.#name.take()
+            }
+        } else {
+            quote! {
+                #(#cfg_attrs)*
+                #name: config_opts.#name.take().unwrap_or_default()
+            }
+        }
+    });
+
+    let skipped_field_defaults = skipped_fields.iter().map(|field| {
+        let name = &field.ident;
+        let cfg_attrs = cfg_attrs(field);
         quote! {
-            #name: config_opts.#name.take().unwrap_or_default()
+            #(#cfg_attrs)*
+            #name: Default::default()
         }
     });
 
-    let from_impl = quote! {
-        impl From<#config_loader_opts_ident> for #struct_name {
-            fn from(mut config_opts: #config_loader_opts_ident) -> Self {
-                Self {
-                    #(#from_impl_fields,)*
-                }
+    // Required and flattened fields make the conversion fallible, so they get
+    // TryFrom in place of From; the loader goes through into_config either way. With
+    // #[load_config(validate = "path::to::fn")] every loaded config is passed
+    // to `fn(&Self) -> Result<(), String>` before it is handed out.
+    let fallible = field_attrs.iter().any(|attrs| attrs.required || attrs.flatten);
+    let into_config_body = match (&struct_attrs.validate, fallible) {
+        (Some(validate), fallible) => {
+            let convert = if fallible {
+                quote! { #struct_name::try_from(self)? }
+            } else {
+                quote! { #struct_name::from(self) }
+            };
+            quote! {
+                let config = #convert;
+                #validate(&config).map_err(::config_loader_trait::ConfigError::Validation)?;
+                Ok(config)
             }
         }
+        (None, true) => quote! { #struct_name::try_from(self) },
+        (None, false) => quote! { Ok(self.into()) },
     };
+    let from_impl = if fallible {
+        quote! {
+            impl TryFrom<#config_loader_opts_ident> for #struct_name {
+                type Error = ::config_loader_trait::ConfigError;
 
-    let load_config_impl = {
-        let has_config_field = fields.named.iter().any(|field| {
-            if let Some(ident) = &field.ident {
-                if ident == "config" {
-                    if let syn::Type::Path(type_path) = &field.ty {
-                        return type_path.path.is_ident("String");
+                fn try_from(mut config_opts: #config_loader_opts_ident) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    Ok(Self {
+                        #(#from_impl_fields,)*
+                        #(#skipped_field_defaults,)*
+                    })
+                }
+            }
+
+            impl #config_loader_opts_ident {
+                pub fn into_config(self) -> Result<#struct_name, ::config_loader_trait::ConfigError> {
+                    #into_config_body
+                }
+            }
+        }
+    } else {
+        quote! {
+            impl From<#config_loader_opts_ident> for #struct_name {
+                fn from(mut config_opts: #config_loader_opts_ident) -> Self {
+                    Self {
+                        #(#from_impl_fields,)*
+                        #(#skipped_field_defaults,)*
                     }
                 }
             }
-            false
+
+            impl #config_loader_opts_ident {
+                pub fn into_config(self) -> Result<#struct_name, ::config_loader_trait::ConfigError> {
+                    #into_config_body
+                }
+            }
+        }
+    };
+
+    let to_opts_fields = fields.named.iter().map(|field| {
+        let name = &field.ident;
+        let cfg_attrs = cfg_attrs(field);
+        if let Some(opts_ty) = flattened(field) {
+            if is_option_type(&field.ty) {
+                quote! { #(#cfg_attrs)* #name: value.#name.as_ref().map(#opts_ty::from).unwrap_or_default() }
+            } else {
+                quote! { #(#cfg_attrs)* #name: #opts_ty::from(&value.#name) }
+            }
+        } else if is_option_type(&field.ty) {
+            quote! { #(#cfg_attrs)* #name: value.#name.clone() }
+        } else {
+            quote! { #(#cfg_attrs)* #name: Some(value.#name.clone()) }
+        }
+    });
+
+    let to_opts_impl = quote! {
+        impl From<&#struct_name> for #config_loader_opts_ident {
+            fn from(value: &#struct_name) -> Self {
+                Self {
+                    #(#to_opts_fields,)*
+                    parse_fallbacks: Vec::new(),
+                    cleared: Vec::new(),
+                }
+            }
+        }
+    };
+
+    // Fields are parsed from env with FromStr, and non-Option ones are filled
+    // with unwrap_or_default(). Checking both up front, spanned on the field's
+    // type, turns the confusing errors deep inside the expansion into one
+    // clear message.
+    let loadable_field_checks = fields.named.iter().zip(&field_attrs).filter(|(_, attrs)| !attrs.flatten).map(|(field, attrs)| {
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let trait_ident = format_ident!("LoadableField_{}", name);
+        let message = format!("field `{}: {}` cannot be loaded by LoadConfig", name, type_name(ty));
+        let cfg_attrs = cfg_attrs(field);
+        // List and map values are split on commas, so only their elements are
+        // parsed, and nested maps are deserialized as a whole. Option and
+        // required fields are never defaulted, so they only need FromStr.
+        let (checked_tys, bounds, label) = match (vec_element_type(ty), map_entry_types(ty), generic_inner_type(ty, "Option")) {
+            (Some(element), ..) => (vec![element], quote! { std::str::FromStr }, "list elements need `FromStr`"),
+            (_, Some(_), _) if is_nested_map_type(ty) => (vec![], quote! { std::str::FromStr }, "map keys and values need `FromStr`"),
+            (_, Some((key, value)), _) => (vec![key, value], quote! { std::str::FromStr }, "map keys and values need `FromStr`"),
+            (.., Some(inner)) => (vec![inner], quote! { std::str::FromStr }, "the value inside the `Option` needs `FromStr`"),
+            _ if attrs.required => (vec![ty], quote! { std::str::FromStr }, "needs `FromStr`"),
+            _ => (vec![ty], quote! { Default + std::str::FromStr }, "needs `Default` and `FromStr`"),
+        };
+        let assertion = checked_tys.iter().map(|checked_ty| {
+            quote_spanned! {checked_ty.span()=>
+                let _ = assert_loadable::<#checked_ty>;
+            }
         });
-        if has_config_field {
+        quote! {
+            #(#cfg_attrs)*
+            const _: () = {
+                #[diagnostic::on_unimplemented(
+                    message = #message,
+                    label = #label,
+                    note = "unset fields fall back to `Default::default()` and env values are parsed with `FromStr`"
+                )]
+                #[allow(non_camel_case_types)]
+                trait #trait_ident {}
+                impl<T: #bounds> #trait_ident for T {}
+                const fn assert_loadable<T: #trait_ident>() {}
+                #(#assertion)*
+            };
+        }
+    });
+
+    let update_fields = fields.named.iter().map(|field| {
+        let name = &field.ident;
+        let id = name.as_ref().unwrap().to_string();
+        let cfg_attrs = cfg_attrs(field);
+        // A nested struct is updated by resolving its CLI args over its current
+        // value, so fields left off the command line keep what they had. An
+        // absent optional block is only filled in when one of its args is passed.
+        if let Some(opts_ty) = flattened(field).filter(|_| is_option_type(&field.ty)) {
+            return quote! {
+                #(#cfg_attrs)*
+                if #opts_ty::passed_on_command_line(&matches) {
+                    let current = self.#name.as_ref().map(#opts_ty::from).unwrap_or_default();
+                    self.#name = Some(#opts_ty::resolve(&matches, cli_opts.#name, current)
+                        .into_config()
+                        .map_err(|err| ::config_loader_trait::ConfigError::Nested {
+                            field: #id,
+                            source: Box::new(err),
+                        })?);
+                }
+            };
+        }
+        if let Some(opts_ty) = flattened(field) {
+            return quote! {
+                #(#cfg_attrs)*
+                {
+                    let current = #opts_ty::from(&self.#name);
+                    self.#name = #opts_ty::resolve(&matches, cli_opts.#name, current)
+                        .into_config()
+                        .map_err(|err| ::config_loader_trait::ConfigError::Nested {
+                            field: #id,
+                            source: Box::new(err),
+                        })?;
+                }
+            };
+        }
+        let update = if is_option_type(&field.ty) {
+            quote! { self.#name = cli_opts.#name.take(); }
+        } else {
             quote! {
-                impl ConfigLoader for #struct_name {
-                    fn load_config() -> Result<Self, Box<dyn std::error::Error>> {
-                        let args: Vec<String> = std::env::args().collect();
-                        let default_value_opts = #config_loader_opts_ident::parse_from([] as [&str; 0]);
-                        let cli_opts = #config_loader_opts_ident::parse_from(args.as_slice());
-                        let yml_opts = #config_loader_opts_ident::load_yaml(cli_opts.config.as_deref(), &default_value_opts);
-                        let precedence_opts = #config_loader_opts_ident::merge(&default_value_opts, &yml_opts);
-                        let env_opts = #config_loader_opts_ident::from_env();
-                        let precedence_opts = #config_loader_opts_ident::merge(&precedence_opts, &env_opts);
-                        let final_opts = #config_loader_opts_ident::resolve(&cli_opts, &default_value_opts, &precedence_opts);
-                        Ok(final_opts.into())
+                if let Some(value) = cli_opts.#name.take() {
+                    self.#name = value;
+                }
+            }
+        };
+        let arg_id = clap_arg_id(field);
+        quote! {
+            #(#cfg_attrs)*
+            if #config_loader_opts_ident::on_command_line(&matches, #arg_id) {
+                #update
+            }
+        }
+    });
+
+    let load_config_impl = {
+        let config_list_field = fields.named.iter().find(|field| is_config_list_field(field));
+        let has_config_field = config_list_field.is_none() && fields.named.iter().any(is_config_field);
+        let config_id = fields.named.iter().find(|field| is_config_field(field)).map(clap_arg_id).unwrap_or_default();
+        if let Some(field) = config_list_field {
+            if struct_attrs.dated_config_glob.is_some() || struct_attrs.app_name.is_some() {
+                let message = "dated_config_glob and app_name need a single `config: String` field";
+                return syn::Error::new(field.ident.span(), message).to_compile_error();
+            }
+        }
+        if let Some((_, span)) = &struct_attrs.multi_config_order {
+            if config_list_field.is_none() {
+                return syn::Error::new(*span, "multi_config_order needs a `config: Vec<String>` field").to_compile_error();
+            }
+        }
+        let config_required_check = match struct_attrs.config_required {
+            Some(span) if !has_config_field && config_list_field.is_none() && struct_attrs.dated_config_glob.is_none() && struct_attrs.app_name.is_none() => {
+                return syn::Error::new(span, "config_required needs a `config: String` field, dated_config_glob or app_name").to_compile_error();
+            }
+            Some(_) if config_list_field.is_some() => quote! {},
+            Some(_) => quote! {
+                match &config_path {
+                    Some(config_path) if ctx.file_exists(config_path) => {}
+                    Some(config_path) => {
+                        let message = format!("required config file {} not found", config_path.display());
+                        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message).into());
                     }
+                    None => {
+                        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no required config file found").into());
+                    }
+                }
+            },
+            None => quote! {},
+        };
+        // With #[load_config(profile_field = "profile")] the named field picks
+        // the file section, so it is resolved from the CLI, env and default
+        // before the file is read.
+        let (profile, default_profile) = match &struct_attrs.profile_field {
+            Some(profile_field) => {
+                let field = fields.named.iter().find(|field| field.ident.as_ref().is_some_and(|ident| ident == &profile_field.value()));
+                let Some(field) = field else {
+                    return syn::Error::new(profile_field.span(), format!("no field named `{}`", profile_field.value())).to_compile_error();
+                };
+                if !is_string_type(generic_inner_type(&field.ty, "Option").unwrap_or(&field.ty)) {
+                    return syn::Error::new(profile_field.span(), "profile_field must name a String or Option<String> field").to_compile_error();
                 }
+                let name = &field.ident;
+                let id = clap_arg_id(field);
+                (
+                    quote! {
+                        if #config_loader_opts_ident::on_command_line(&matches, #id) {
+                            cli_opts.#name.as_deref()
+                        } else {
+                            env_opts.#name.as_deref().or(cli_opts.#name.as_deref())
+                        }
+                    },
+                    quote! { default_value_opts.#name.as_deref() },
+                )
+            }
+            None => (quote! { None }, quote! { None }),
+        };
+
+        // An unset --config defers to the config field's env var, so the env
+        // can override the default path but not an explicit flag.
+        let select_config_path = if has_config_field {
+            quote! {
+                let config = if #config_loader_opts_ident::on_command_line(&matches, #config_id) {
+                    cli_opts.config.as_deref()
+                } else {
+                    env_opts.config.as_deref().or(cli_opts.config.as_deref())
+                };
+                let config_path = #config_loader_opts_ident::config_path(ctx, &matches, config);
             }
         } else {
             quote! {
-                impl ConfigLoader for #struct_name {
-                    fn load_config() -> Result<Self, Box<dyn std::error::Error>> {
-                        let args: Vec<String> = std::env::args().collect();
-                        let default_value_opts = #config_loader_opts_ident::parse_from([] as [&str; 0]);
-                        let cli_opts = #config_loader_opts_ident::parse_from(args.as_slice());
-                        let env_opts = #config_loader_opts_ident::from_env();
-                        let precedence_opts = #config_loader_opts_ident::merge(&default_value_opts, &env_opts);
-                        let final_opts = #config_loader_opts_ident::resolve(&cli_opts, &default_value_opts, &precedence_opts);
-                        Ok(final_opts.into())
+                let config_path = #config_loader_opts_ident::config_path(ctx, &matches, None);
+            }
+        };
+        // With `config: Vec<String>` every listed file is loaded, later files
+        // overriding earlier ones, or earlier ones with multi_config_order =
+        // "first_wins"; a `~` in an overriding file resets a field, missing
+        // files are skipped with a warning, or fail with config_required.
+        // raw_config_value merges every existing one.
+        let (read_raw_config, load_file_layer) = match config_list_field {
+            Some(_) => {
+                let missing_config_file = if struct_attrs.config_required.is_some() {
+                    quote! {
+                        let message = format!("required config file {} not found", config_path.display());
+                        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message).into());
+                    }
+                } else {
+                    quote! {
+                        eprintln!("warning: config file {} not found, skipping", config_path.display());
+                        continue;
+                    }
+                };
+                let fold_order = match &struct_attrs.multi_config_order {
+                    Some((MultiConfigOrder::FirstWins, _)) => quote! { .rev() },
+                    _ => quote! {},
+                };
+                let select_config_paths = quote! {
+                    let config_paths = if #config_loader_opts_ident::on_command_line(&matches, #config_id) {
+                        cli_opts.config.clone()
+                    } else {
+                        env_opts.config.clone().or_else(|| cli_opts.config.clone())
+                    };
+                    let config_paths = config_paths
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|config_path| ctx.cwd.join(config_path))
+                        #fold_order
+                        .collect::<Vec<_>>();
+                };
+                (
+                    quote! {
+                        #select_config_paths
+                        let mut merged = None;
+                        for config_path in &config_paths {
+                            if let Some(value) = #config_loader_opts_ident::read_yaml_value(ctx, Some(config_path))? {
+                                merged = Some(match merged {
+                                    Some(merged) => #config_loader_opts_ident::merge_yaml_values(merged, value),
+                                    None => value,
+                                });
+                            }
+                        }
+                        Ok(merged)
+                    },
+                    quote! {
+                        #select_config_paths
+                        let mut file_opts = #config_loader_opts_ident::default();
+                        for config_path in &config_paths {
+                            if !ctx.file_exists(config_path) {
+                                #missing_config_file
+                            }
+                            let layer_opts = #config_loader_opts_ident::load_yaml(ctx, Some(config_path), #profile)?;
+                            file_opts = #config_loader_opts_ident::merge(file_opts, layer_opts);
+                        }
+                    },
+                )
+            }
+            None => (
+                quote! {
+                    #select_config_path
+                    #config_loader_opts_ident::read_yaml_value(ctx, config_path.as_deref())
+                },
+                quote! {
+                    #select_config_path
+                    #config_required_check
+                    let file_opts = #config_loader_opts_ident::load_yaml(ctx, config_path.as_deref(), #profile)?;
+                },
+            ),
+        };
+        let config_path = match (has_config_field, &struct_attrs.dated_config_glob) {
+            (true, Some(_)) => quote! {
+                if #config_loader_opts_ident::on_command_line(matches, #config_id) {
+                    return config.map(|config_path| ctx.cwd.join(config_path));
+                }
+                #config_loader_opts_ident::dated_config_path(ctx)
+                    .or_else(|| config.map(|config_path| ctx.cwd.join(config_path)))
+            },
+            (true, None) => quote! { config.map(|config_path| ctx.cwd.join(config_path)) },
+            (false, Some(_)) => quote! { #config_loader_opts_ident::dated_config_path(ctx) },
+            (false, None) => quote! { None },
+        };
+
+        // With #[load_config(app_name = "myapp")] a config path that was not
+        // passed explicitly and does not exist falls back to the first existing
+        // `$XDG_CONFIG_HOME/myapp/config.yml` or `~/.config/myapp/config.yml`.
+        let (config_path, discovered_config_path_function) = match &struct_attrs.app_name {
+            Some(app_name) => {
+                let explicit = if has_config_field {
+                    quote! { #config_loader_opts_ident::on_command_line(matches, #config_id) }
+                } else {
+                    quote! { false }
+                };
+                let discovering_config_path = quote! {
+                    let config_path = #config_loader_opts_ident::local_config_path(ctx, matches, config);
+                    if #explicit || config_path.as_deref().is_some_and(|config_path| ctx.file_exists(config_path)) {
+                        return config_path;
+                    }
+                    #config_loader_opts_ident::discovered_config_path(ctx).or(config_path)
+                };
+                let functions = quote! {
+                    pub fn local_config_path(ctx: &::config_loader_trait::LoadContext, matches: &clap::ArgMatches, config: Option<&str>) -> Option<std::path::PathBuf> {
+                        #config_path
+                    }
+
+                    pub fn discovered_config_path(ctx: &::config_loader_trait::LoadContext) -> Option<std::path::PathBuf> {
+                        let xdg_config_home = ctx.env.get("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()).map(std::path::PathBuf::from);
+                        let home_config = ctx.env.get("HOME").map(|home| std::path::Path::new(home).join(".config"));
+                        [xdg_config_home, home_config]
+                            .into_iter()
+                            .flatten()
+                            .map(|dir| dir.join(#app_name).join("config.yml"))
+                            .find(|config_path| ctx.file_exists(config_path))
+                    }
+                };
+                (discovering_config_path, functions)
+            }
+            None => (config_path, quote! {}),
+        };
+
+        // With #[load_config(dated_config_glob = "config.*.yml")] the `*` must
+        // match a YYYY-MM-DD date; the latest file dated on or before the
+        // context clock's (UTC) day is active, unless --config was passed.
+        let dated_config_path_function = match &struct_attrs.dated_config_glob {
+            Some(glob) => quote! {
+                pub fn dated_config_path(ctx: &::config_loader_trait::LoadContext) -> Option<std::path::PathBuf> {
+                    let pattern = ctx.cwd.join(#glob);
+                    let (prefix, suffix) = pattern.file_name()?.to_str()?.split_once('*')?;
+                    let today = #config_loader_opts_ident::civil_date(ctx.now);
+                    let dir = pattern.parent()?;
+                    ctx.dir_entries(dir)
+                        .ok()?
+                        .into_iter()
+                        .filter_map(|file_name| {
+                            let date = file_name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                            let date = #config_loader_opts_ident::parse_config_date(date)?;
+                            (date <= today).then(|| (date, dir.join(&file_name)))
+                        })
+                        .max_by_key(|(date, _)| *date)
+                        .map(|(_, path)| path)
+                }
+
+                pub fn parse_config_date(date: &str) -> Option<(i64, u32, u32)> {
+                    let mut parts = date.splitn(3, '-');
+                    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+                    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+                        return None;
+                    }
+                    let date = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+                    ((1..=12).contains(&date.1) && (1..=31).contains(&date.2)).then_some(date)
+                }
+
+                // Days-from-epoch to proleptic Gregorian (year, month, day), UTC.
+                pub fn civil_date(now: std::time::SystemTime) -> (i64, u32, u32) {
+                    let secs = now.duration_since(std::time::UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or_default();
+                    let z = (secs / 86_400) as i64 + 719_468;
+                    let era = z.div_euclid(146_097);
+                    let doe = z - era * 146_097;
+                    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+                    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+                    let mp = (5 * doy + 2) / 153;
+                    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+                    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+                    let year = yoe + era * 400 + i64::from(month <= 2);
+                    (year, month, day)
+                }
+            },
+            None => quote! {},
+        };
+        quote! {
+            impl #config_loader_opts_ident {
+                pub fn config_path(ctx: &::config_loader_trait::LoadContext, matches: &clap::ArgMatches, config: Option<&str>) -> Option<std::path::PathBuf> {
+                    #config_path
+                }
+
+                #discovered_config_path_function
+
+                #dated_config_path_function
+
+                pub fn raw_config_value(ctx: &::config_loader_trait::LoadContext) -> Result<Option<serde_yaml::Value>, ::config_loader_trait::ConfigError> {
+                    let (cli_opts, matches) = #config_loader_opts_ident::parse_cli(ctx.args.as_slice())?;
+                    let env_opts = #config_loader_opts_ident::env_layer(ctx, #env_prefix)?;
+                    #read_raw_config
+                }
+
+                pub fn load(ctx: &::config_loader_trait::LoadContext, prefix: &str, source: Option<&dyn ::config_loader_trait::ConfigSource>) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    Ok(#config_loader_opts_ident::load_with_sources(ctx, prefix, source)?.0)
+                }
+
+                pub fn load_with_sources(
+                    ctx: &::config_loader_trait::LoadContext,
+                    prefix: &str,
+                    source: Option<&dyn ::config_loader_trait::ConfigSource>,
+                ) -> Result<(Self, std::collections::HashMap<String, ::config_loader_trait::Source>), ::config_loader_trait::ConfigError> {
+                    let (cli_opts, matches) = #config_loader_opts_ident::parse_cli(ctx.args.as_slice())?;
+                    let env_opts = #config_loader_opts_ident::env_layer(ctx, prefix)?;
+                    #load_file_layer
+                    let param_opts = match source {
+                        Some(source) => #config_loader_opts_ident::from_source(source)?,
+                        None => #config_loader_opts_ident::default(),
+                    };
+                    let sources = #config_loader_opts_ident::value_sources(&matches, &file_opts, &param_opts, &env_opts);
+                    let precedence_opts = #config_loader_opts_ident::merge(#config_loader_opts_ident::merge(file_opts, param_opts), env_opts);
+                    let mut final_opts = #config_loader_opts_ident::resolve(&matches, cli_opts, precedence_opts);
+                    final_opts.resolve_field_refs()?;
+                    final_opts.check_dynamic_values(&ctx.args)?;
+                    Ok((final_opts, sources))
+                }
+            }
+
+            impl ::config_loader_trait::ConfigLoader for #struct_name {
+                fn load_config() -> Result<Self, ::config_loader_trait::ConfigError> {
+                    Self::load_config_with_prefix(#env_prefix)
+                }
+
+                fn load_config_with_prefix(prefix: &str) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    #config_loader_opts_ident::exit_on_args_error(#config_loader_opts_ident::load(&::config_loader_trait::LoadContext::from_process(), prefix, None))?.into_config()
+                }
+
+                fn load_config_with_source(source: &dyn ::config_loader_trait::ConfigSource) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    #config_loader_opts_ident::exit_on_args_error(#config_loader_opts_ident::load(&::config_loader_trait::LoadContext::from_process(), #env_prefix, Some(source)))?.into_config()
+                }
+
+                fn load_config_with_context(ctx: &::config_loader_trait::LoadContext) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    #config_loader_opts_ident::load(ctx, #env_prefix, None)?.into_config()
+                }
+
+                fn load_config_with_sources() -> Result<(Self, std::collections::HashMap<String, ::config_loader_trait::Source>), ::config_loader_trait::ConfigError> {
+                    let (opts, sources) =
+                        #config_loader_opts_ident::exit_on_args_error(#config_loader_opts_ident::load_with_sources(&::config_loader_trait::LoadContext::from_process(), #env_prefix, None))?;
+                    Ok((opts.into_config()?, sources))
+                }
+
+                fn load_config_from_args(args: &[String]) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    let ctx = ::config_loader_trait::LoadContext {
+                        args: args.to_vec(),
+                        ..::config_loader_trait::LoadContext::from_process()
+                    };
+                    Self::load_config_with_context(&ctx)
+                }
+
+                fn load_config_from(
+                    args: &[String],
+                    env: &std::collections::HashMap<String, String>,
+                    read: impl Fn(&str) -> std::io::Result<String>,
+                ) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    let ctx = ::config_loader_trait::LoadContext {
+                        args: args.to_vec(),
+                        env: env.clone(),
+                        read_file: Some(std::rc::Rc::new(move |path: &std::path::Path| read(&path.to_string_lossy()))),
+                        ..::config_loader_trait::LoadContext::from_process()
+                    };
+                    Self::load_config_with_context(&ctx)
+                }
+
+                fn default_values() -> Result<Self, ::config_loader_trait::ConfigError> {
+                    let (default_value_opts, matches) = #config_loader_opts_ident::parse_cli(&[])?;
+                    #config_loader_opts_ident::resolve(&matches, default_value_opts, #config_loader_opts_ident::default()).into_config()
+                }
+
+                // Only the clap defaults and the given file; argv and the process
+                // env are not consulted.
+                fn config_values(path: impl AsRef<std::path::Path>) -> Result<Self, ::config_loader_trait::ConfigError> {
+                    let ctx = ::config_loader_trait::LoadContext {
+                        args: Vec::new(),
+                        env: std::collections::HashMap::new(),
+                        ..::config_loader_trait::LoadContext::from_process()
+                    };
+                    let path = path.as_ref();
+                    if !path.exists() {
+                        let message = format!("config file {} not found", path.display());
+                        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message).into());
                     }
+                    let (default_value_opts, matches) = #config_loader_opts_ident::parse_cli(&[])?;
+                    let file_opts = #config_loader_opts_ident::load_yaml(&ctx, Some(path), #default_profile)?;
+                    #config_loader_opts_ident::resolve(&matches, default_value_opts, file_opts).into_config()
+                }
+
+                fn list_profiles(path: &std::path::Path) -> Result<Vec<String>, ::config_loader_trait::ConfigError> {
+                    let ctx = ::config_loader_trait::LoadContext {
+                        args: Vec::new(),
+                        env: std::collections::HashMap::new(),
+                        ..::config_loader_trait::LoadContext::from_process()
+                    };
+                    if !path.exists() {
+                        let message = format!("config file {} not found", path.display());
+                        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, message).into());
+                    }
+                    #config_loader_opts_ident::list_profiles(&ctx, path)
+                }
+
+                fn describe() -> ::config_loader_trait::ConfigDescription {
+                    #config_loader_opts_ident::describe()
+                }
+
+                fn dump_minimal_config(&self) -> Result<String, ::config_loader_trait::ConfigError> {
+                    #config_loader_opts_ident::from(self).to_minimal_yaml()
+                }
+
+                fn raw_config_value() -> Result<Option<serde_yaml::Value>, ::config_loader_trait::ConfigError> {
+                    #config_loader_opts_ident::exit_on_args_error(#config_loader_opts_ident::raw_config_value(&::config_loader_trait::LoadContext::from_process()))
+                }
+
+                fn config_hash(&self) -> u64 {
+                    #config_loader_opts_ident::from(self).config_hash()
+                }
+
+                fn to_canonical_yaml(&self) -> Result<String, ::config_loader_trait::ConfigError> {
+                    #config_loader_opts_ident::from(self).to_canonical_yaml()
+                }
+
+                fn dump_config(&self) -> Result<String, ::config_loader_trait::ConfigError> {
+                    self.dump_config_as(::config_loader_trait::ConfigFormat::Yaml)
+                }
+
+                fn dump_config_as(&self, format: ::config_loader_trait::ConfigFormat) -> Result<String, ::config_loader_trait::ConfigError> {
+                    #config_loader_opts_ident::from(self).dump(format)
+                }
+
+                fn update_from_args(&mut self, args: &[String]) -> Result<(), ::config_loader_trait::ConfigError> {
+                    let matches = <#config_loader_opts_ident as clap::CommandFactory>::command().try_get_matches_from(args)?;
+                    let mut cli_opts = <#config_loader_opts_ident as clap::FromArgMatches>::from_arg_matches(&matches)?;
+                    #(#update_fields)*
+                    Ok(())
+                }
+
+                fn init_config(path: &std::path::Path, overwrite: bool) -> Result<(), ::config_loader_trait::ConfigError> {
+                    use std::io::Write;
+                    let contents = #config_loader_opts_ident::default_config_yaml()?;
+                    let mut file = std::fs::OpenOptions::new()
+                        .write(true)
+                        .truncate(true)
+                        .create(overwrite)
+                        .create_new(!overwrite)
+                        .open(path)?;
+                    file.write_all(contents.as_bytes())?;
+                    Ok(())
                 }
             }
         }
     };
 
     quote! {
-        #config_loader_trait
+        #(#loadable_field_checks)*
         #config_loader_opts_impl
+        #struct_debug_impl
         #from_impl
+        #to_opts_impl
         #load_config_impl
     }
 }
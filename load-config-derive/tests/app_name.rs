@@ -0,0 +1,55 @@
+use clap::Parser;
+use config_loader_trait::{ConfigLoader, LoadContext};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(app_name = "myapp")]
+struct Opts {
+    #[clap(short, long, default_value = "config.yml")]
+    config: String,
+
+    #[clap(long)]
+    host: Option<String>,
+}
+
+// `files` are the paths that exist, each with the host it sets.
+fn load(args: &[&str], env: &[(&str, &str)], files: &'static [(&'static str, &'static str)]) -> Opts {
+    let ctx = LoadContext {
+        args: common::args(args),
+        env: common::env(env),
+        cwd: "/work".into(),
+        read_file: Some(std::rc::Rc::new(move |path: &std::path::Path| {
+            files
+                .iter()
+                .find(|(file, _)| path.to_str() == Some(*file))
+                .map(|(_, host)| format!("host: {host}\n"))
+                .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        })),
+        ..LoadContext::from_process()
+    };
+    Opts::load_config_with_context(&ctx).unwrap()
+}
+
+#[test]
+fn a_missing_local_config_falls_back_to_the_xdg_dir() {
+    let files = &[("/xdg/myapp/config.yml", "xdg"), ("/home/me/.config/myapp/config.yml", "home")];
+    let opts = load(&["app"], &[("XDG_CONFIG_HOME", "/xdg"), ("HOME", "/home/me")], files);
+    assert_eq!(opts.host.as_deref(), Some("xdg"));
+    let opts = load(&["app"], &[("HOME", "/home/me")], files);
+    assert_eq!(opts.host.as_deref(), Some("home"));
+}
+
+#[test]
+fn an_existing_local_config_wins() {
+    let files = &[("/work/config.yml", "local"), ("/home/me/.config/myapp/config.yml", "home")];
+    assert_eq!(load(&["app"], &[("HOME", "/home/me")], files).host.as_deref(), Some("local"));
+}
+
+#[test]
+fn an_explicit_config_is_never_replaced() {
+    let files = &[("/home/me/.config/myapp/config.yml", "home")];
+    assert_eq!(load(&["app", "--config", "missing.yml"], &[("HOME", "/home/me")], files).host, None);
+}
@@ -0,0 +1,85 @@
+use clap::Parser;
+use config_loader_trait::assert_config_matches;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, PartialEq, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    host: Option<String>,
+
+    #[clap(long, default_value = "80")]
+    port: u16,
+
+    #[clap(long)]
+    user: Option<String>,
+}
+
+#[test]
+fn each_layer_lands_where_expected() {
+    assert_config_matches!(
+        Opts,
+        args: ["app", "--port", "8080"],
+        env: [("HOST", "env.example.com")],
+        file: "host: file.example.com\nport: 9090\nuser: admin\n",
+        Opts {
+            config: "/config.yml".to_string(),
+            host: Some("env.example.com".to_string()),
+            port: 8080,
+            user: Some("admin".to_string()),
+        },
+    );
+}
+
+#[test]
+fn no_inputs_leave_the_defaults() {
+    assert_config_matches!(
+        Opts,
+        args: ["app"],
+        env: [],
+        file: "",
+        Opts {
+            config: "/config.yml".to_string(),
+            host: None,
+            port: 80,
+            user: None,
+        },
+    );
+}
+
+#[test]
+#[should_panic(expected = "  Opts {\n      config: \"/config.yml\",\n-     host: None,\n+     host: Some(\n+         \"file.example.com\",\n+     ),\n")]
+fn a_mismatch_shows_a_diff() {
+    assert_config_matches!(
+        Opts,
+        args: ["app"],
+        env: [],
+        file: "host: file.example.com\n",
+        Opts {
+            config: "/config.yml".to_string(),
+            host: None,
+            port: 80,
+            user: None,
+        },
+    );
+}
+
+#[test]
+#[should_panic(expected = "failed to load Opts")]
+fn a_load_error_fails_the_assertion() {
+    assert_config_matches!(
+        Opts,
+        args: ["app", "--port", "not-a-port"],
+        env: [],
+        file: "",
+        Opts {
+            config: "/config.yml".to_string(),
+            host: None,
+            port: 80,
+            user: None,
+        },
+    );
+}
@@ -0,0 +1,37 @@
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    verbose: bool,
+}
+
+fn load(verbose: &str, file: &str) -> Opts {
+    Opts::load_config_from(&common::args(&["app"]), &common::env(&[("VERBOSE", verbose)]), common::reader(file))
+    .unwrap()
+}
+
+#[test]
+fn verbose_1_is_true() {
+    assert!(load("1", "").verbose);
+    assert!(load("YES", "").verbose);
+}
+
+#[test]
+fn verbose_off_is_false() {
+    assert!(!load("off", "verbose: true\n").verbose);
+}
+
+#[test]
+fn verbose_garbage_falls_through_to_the_file() {
+    assert!(load("garbage", "verbose: true\n").verbose);
+    assert!(!load("garbage", "").verbose);
+}
@@ -0,0 +1,72 @@
+// `#[cfg(test)]` and `#[cfg(not(test))]` stand in for a feature that is on and
+// one that is off, so both states are compiled in one build.
+
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+mod db {
+    use clap::Parser;
+    use load_config_derive::LoadConfig;
+    use serde::Deserialize;
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    pub struct Database {
+        #[clap(long)]
+        pub url: Option<String>,
+    }
+}
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[cfg(test)]
+    #[clap(long)]
+    enabled: Option<String>,
+
+    #[cfg(not(test))]
+    #[clap(long)]
+    disabled: Option<String>,
+
+    #[cfg(not(test))]
+    #[clap(long)]
+    #[load_config(required)]
+    disabled_required: String,
+
+    #[cfg(not(test))]
+    #[clap(flatten)]
+    database: db::Database,
+}
+
+fn load(cli: &[&str], env: &[(&str, &str)], file: &str) -> Result<Opts, ConfigError> {
+    Opts::load_config_from(&common::args(cli), &common::env(env), common::reader(file))
+}
+
+#[test]
+fn an_enabled_field_loads_from_every_layer() {
+    assert_eq!(load(&["app"], &[], "enabled: file\n").unwrap().enabled.as_deref(), Some("file"));
+    assert_eq!(load(&["app"], &[("ENABLED", "env")], "").unwrap().enabled.as_deref(), Some("env"));
+    assert_eq!(load(&["app", "--enabled", "cli"], &[], "").unwrap().enabled.as_deref(), Some("cli"));
+}
+
+#[test]
+fn a_disabled_field_is_not_an_arg() {
+    assert!(matches!(load(&["app", "--disabled", "cli"], &[], ""), Err(ConfigError::Args(_))));
+}
+
+#[test]
+fn a_disabled_required_field_does_not_fail_the_load() {
+    assert!(load(&["app"], &[("DISABLED_REQUIRED", "env")], "disabled: file\n").is_ok());
+}
+
+#[test]
+fn only_enabled_fields_are_described() {
+    let description = Opts::describe();
+    let names = description.fields.iter().map(|field| field.name).collect::<Vec<_>>();
+    assert_eq!(names, ["config", "enabled"]);
+}
@@ -0,0 +1,51 @@
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, default_value = "42")]
+    age: u8,
+
+    #[clap(long = "user", id = "user_name", default_value = "nobody")]
+    name: String,
+
+    #[clap(skip)]
+    computed: Option<String>,
+}
+
+fn load(cli: &[&str]) -> Opts {
+    let file = "age: 7\nname: file\ncomputed: file\n";
+    Opts::load_config_from(&common::args(cli), &common::env(&[]), common::reader(file))
+    .unwrap()
+}
+
+#[test]
+fn an_explicit_cli_value_equal_to_the_default_still_wins() {
+    assert_eq!(load(&["app", "--age", "42"]).age, 42);
+    assert_eq!(load(&["app"]).age, 7);
+}
+
+#[test]
+fn fields_that_are_not_clap_args_load_from_other_layers() {
+    assert_eq!(load(&["app"]).computed.as_deref(), Some("file"));
+}
+
+#[test]
+fn args_with_a_custom_id_are_checked_under_that_id() {
+    assert_eq!(load(&["app", "--user", "nobody"]).name, "nobody");
+    assert_eq!(load(&["app"]).name, "file");
+}
+
+#[test]
+fn describe_finds_defaults_under_the_custom_id() {
+    let description = Opts::describe();
+    let name = description.fields.iter().find(|field| field.name == "name").unwrap();
+    assert_eq!(name.default.as_deref(), Some("nobody"));
+}
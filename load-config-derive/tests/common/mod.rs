@@ -0,0 +1,39 @@
+// Builds the args, env and config file the tests load their structs from.
+// Each test crate uses only some of these.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
+use config_loader_trait::LoadContext;
+
+// The config path the test structs default to, the only one reader serves.
+pub const CONFIG_PATH: &str = "/config.yml";
+
+pub fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+pub fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+    vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+// A load_config_from reader serving `file` at CONFIG_PATH; every other path
+// is missing.
+pub fn reader(file: &str) -> impl Fn(&str) -> std::io::Result<String> + '_ {
+    move |path| match path {
+        CONFIG_PATH => Ok(file.to_string()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    }
+}
+
+// The LoadContext equivalent of args, env and reader.
+pub fn context<'a>(cli: &[&str], vars: &[(&str, &str)], file: &'a str) -> LoadContext<'a> {
+    LoadContext {
+        args: args(cli),
+        env: env(vars),
+        read_file: Some(Rc::new(move |path: &Path| reader(file)(path.to_str().unwrap_or_default()))),
+        ..LoadContext::from_process()
+    }
+}
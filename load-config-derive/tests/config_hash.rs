@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::{Deserialize, Serialize};
+
+#[derive(Parser, Deserialize, Serialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, value_parser = parse_label)]
+    labels: HashMap<String, String>,
+}
+
+fn parse_label(value: &str) -> Result<(String, String), String> {
+    value.split_once('=').map(|(key, value)| (key.to_string(), value.to_string())).ok_or_else(|| format!("expected KEY=VALUE, got {value}"))
+}
+
+fn with_labels(labels: &[(&str, &str)]) -> Opts {
+    Opts {
+        config: "/config.yml".to_string(),
+        labels: labels.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+    }
+}
+
+#[test]
+fn hash_map_fields_hash_the_same_in_any_order() {
+    let labels = (0..64).map(|i| (format!("key{i}"), format!("value{i}"))).collect::<Vec<_>>();
+    let labels = labels.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect::<Vec<_>>();
+    let reversed = labels.iter().rev().copied().collect::<Vec<_>>();
+    // Each HashMap gets its own random hasher state, so iteration orders differ.
+    let hashes = (0..8).map(|_| with_labels(&labels).config_hash()).chain((0..8).map(|_| with_labels(&reversed).config_hash()));
+    let expected = with_labels(&labels).config_hash();
+    assert!(hashes.into_iter().all(|hash| hash == expected));
+}
+
+#[test]
+fn different_values_hash_differently() {
+    assert_ne!(with_labels(&[("a", "1")]).config_hash(), with_labels(&[("a", "2")]).config_hash());
+}
@@ -0,0 +1,30 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(config_required)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    host: Option<String>,
+}
+
+#[test]
+fn an_existing_config_loads() {
+    let opts = Opts::load_config_with_context(&common::context(&["app"], &[], "host: example.com\n")).unwrap();
+    assert_eq!(opts.host.as_deref(), Some("example.com"));
+}
+
+#[test]
+fn a_missing_config_is_an_error() {
+    let ctx = common::context(&["app", "--config", "/missing.yml"], &[], "host: example.com\n");
+    let err = Opts::load_config_with_context(&ctx).unwrap_err();
+    assert!(matches!(&err, ConfigError::Io(err) if err.kind() == std::io::ErrorKind::NotFound), "{err:?}");
+    assert_eq!(err.to_string(), "required config file /missing.yml not found");
+}
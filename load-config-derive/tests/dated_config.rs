@@ -0,0 +1,56 @@
+use clap::Parser;
+use config_loader_trait::{ConfigLoader, LoadContext};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(dated_config_glob = "/etc/app/config.*.yml")]
+struct Opts {
+    #[clap(long)]
+    release: Option<String>,
+}
+
+fn read(path: &std::path::Path) -> std::io::Result<String> {
+    let release = match path.to_str() {
+        Some("/etc/app/config.2024-01-01.yml") => "january",
+        Some("/etc/app/config.2024-06-01.yml") => "june",
+        Some("/etc/app/config.2024-07-01.yml") => "july",
+        _ => return Err(std::io::ErrorKind::NotFound.into()),
+    };
+    Ok(format!("release: {release}\n"))
+}
+
+fn read_dir(path: &std::path::Path) -> std::io::Result<Vec<String>> {
+    match path.to_str() {
+        Some("/etc/app") => Ok(["config.2024-01-01.yml", "config.2024-06-01.yml", "config.2024-07-01.yml", "config.latest.yml", "notes.txt"]
+            .map(str::to_string)
+            .to_vec()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    }
+}
+
+// 2024-06-15T00:00:00Z.
+const JUNE_15: u64 = 1_718_409_600;
+
+fn load(now: u64) -> Opts {
+    let ctx = LoadContext {
+        args: vec!["app".to_string()],
+        env: Default::default(),
+        now: std::time::UNIX_EPOCH + std::time::Duration::from_secs(now),
+        read_file: Some(std::rc::Rc::new(read)),
+        read_dir: Some(std::rc::Rc::new(read_dir)),
+        ..LoadContext::from_process()
+    };
+    Opts::load_config_with_context(&ctx).unwrap()
+}
+
+#[test]
+fn the_latest_config_not_after_today_is_loaded() {
+    assert_eq!(load(JUNE_15).release.as_deref(), Some("june"));
+    assert_eq!(load(JUNE_15 + 30 * 86_400).release.as_deref(), Some("july"));
+}
+
+#[test]
+fn no_config_is_loaded_before_the_first_date() {
+    assert_eq!(load(0).release, None);
+}
@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, HashMap};
+
+use clap::Parser;
+use config_loader_trait::{ConfigLoader, LoadContext};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_values = ["/base.yml", "/local.yml"])]
+    config: Vec<String>,
+
+    #[clap(skip)]
+    #[load_config(merge = "deep")]
+    services: HashMap<String, HashMap<String, String>>,
+
+    #[clap(skip)]
+    #[load_config(merge = "deep")]
+    extra: BTreeMap<String, serde_yaml::Value>,
+
+    #[clap(skip)]
+    #[load_config(merge = "deep")]
+    labels: BTreeMap<String, String>,
+}
+
+const BASE: &str = "
+services:
+  api:
+    host: base
+    port: \"80\"
+  db:
+    host: db
+extra:
+  logging:
+    level: info
+    targets: [stdout]
+labels:
+  team: core
+";
+
+fn load(local: &'static str, env: &[(&str, &str)]) -> Opts {
+    let ctx = LoadContext {
+        args: vec!["app".to_string()],
+        env: env.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        read_file: Some(std::rc::Rc::new(move |path: &std::path::Path| match path.to_str() {
+            Some("/base.yml") => Ok(BASE.to_string()),
+            Some("/local.yml") => Ok(local.to_string()),
+            _ => Err(std::io::ErrorKind::NotFound.into()),
+        })),
+        ..LoadContext::from_process()
+    };
+    Opts::load_config_with_context(&ctx).unwrap()
+}
+
+#[test]
+fn nested_maps_merge_level_by_level() {
+    let opts = load("services:\n  api:\n    port: \"8080\"\n  cache:\n    host: redis\n", &[]);
+    assert_eq!(opts.services["api"], HashMap::from([("host".to_string(), "base".to_string()), ("port".to_string(), "8080".to_string())]));
+    assert_eq!(opts.services["db"]["host"], "db");
+    assert_eq!(opts.services["cache"]["host"], "redis");
+}
+
+#[test]
+fn yaml_values_merge_like_layered_files() {
+    let opts = load("extra:\n  logging:\n    level: debug\n", &[]);
+    let expected: serde_yaml::Value = serde_yaml::from_str("level: debug\ntargets: [stdout]\n").unwrap();
+    assert_eq!(opts.extra["logging"], expected);
+}
+
+#[test]
+fn flat_maps_keep_the_higher_layers_values() {
+    let opts = load("labels:\n  team: web\n  tier: front\n", &[]);
+    assert_eq!(opts.labels, BTreeMap::from([("team".to_string(), "web".to_string()), ("tier".to_string(), "front".to_string())]));
+}
+
+#[test]
+fn env_merges_a_yaml_document_into_the_file() {
+    let opts = load("", &[("SERVICES", "{api: {host: env}}")]);
+    assert_eq!(opts.services["api"]["host"], "env");
+    assert_eq!(opts.services["api"]["port"], "80");
+    assert_eq!(opts.services["db"]["host"], "db");
+}
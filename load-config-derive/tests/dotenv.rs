@@ -0,0 +1,55 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader, LoadContext};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(dotenv = ".env")]
+struct Opts {
+    #[clap(long)]
+    host: Option<String>,
+
+    #[clap(long)]
+    token: Option<String>,
+
+    #[clap(long, default_value = "1")]
+    workers: u32,
+}
+
+fn load(env: &[(&str, &str)], dotenv: Option<&'static str>) -> Result<Opts, ConfigError> {
+    let ctx = LoadContext {
+        args: vec!["app".to_string()],
+        env: common::env(env),
+        cwd: "/srv/app".into(),
+        read_file: Some(std::rc::Rc::new(move |path: &std::path::Path| match (path.to_str(), dotenv) {
+            (Some("/srv/app/.env"), Some(dotenv)) => Ok(dotenv.to_string()),
+            _ => Err(std::io::ErrorKind::NotFound.into()),
+        })),
+        ..LoadContext::from_process()
+    };
+    Opts::load_config_with_context(&ctx)
+}
+
+#[test]
+fn dotenv_entries_sit_beneath_the_real_env() {
+    let dotenv = "# local overrides\nHOST=dotenv.example.com\nexport TOKEN=\"abc 123\"\n\nWORKERS=2\n";
+    let opts = load(&[("WORKERS", "8")], Some(dotenv)).unwrap();
+    assert_eq!(opts.host.as_deref(), Some("dotenv.example.com"));
+    assert_eq!(opts.token.as_deref(), Some("abc 123"));
+    assert_eq!(opts.workers, 8);
+}
+
+#[test]
+fn a_missing_dotenv_is_empty() {
+    let opts = load(&[("HOST", "env.example.com")], None).unwrap();
+    assert_eq!(opts.host.as_deref(), Some("env.example.com"));
+    assert_eq!(opts.workers, 1);
+}
+
+#[test]
+fn a_malformed_line_is_invalid() {
+    let err = load(&[], Some("HOST=ok\njust words\n")).unwrap_err();
+    assert!(matches!(&err, ConfigError::Invalid(message) if message == "/srv/app/.env:2: expected KEY=VALUE"), "{err:?}");
+}
@@ -0,0 +1,50 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    allowed_levels: Vec<String>,
+
+    #[clap(long)]
+    #[load_config(dynamic_values_from = "allowed_levels")]
+    level: Option<String>,
+}
+
+fn load(cli: &[&str], file: &str) -> Result<Opts, ConfigError> {
+    Opts::load_config_from(&common::args(cli), &common::env(&[]), common::reader(file))
+}
+
+const FILE: &str = "allowed-levels: [low, high]\n";
+
+#[test]
+fn values_listed_in_the_file_are_accepted() {
+    assert_eq!(load(&["app", "--level", "high"], FILE).unwrap().level.as_deref(), Some("high"));
+}
+
+#[test]
+fn values_missing_from_the_file_are_rejected() {
+    let err = load(&["app", "--level", "medium"], FILE).unwrap_err();
+    let ConfigError::Args(err) = &err else {
+        panic!("expected an args error, got {err:?}");
+    };
+    assert_eq!(err.kind(), clap::error::ErrorKind::ValueValidation);
+    assert!(err.to_string().contains("expected one of: low, high"), "{err}");
+}
+
+#[test]
+fn without_a_list_any_value_is_accepted() {
+    assert_eq!(load(&["app", "--level", "medium"], "").unwrap().level.as_deref(), Some("medium"));
+}
+
+#[test]
+fn only_the_command_line_is_checked() {
+    assert_eq!(load(&["app"], "allowed-levels: [low]\nlevel: other\n").unwrap().level.as_deref(), Some("other"));
+}
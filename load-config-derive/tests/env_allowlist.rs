@@ -0,0 +1,27 @@
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(env_prefix = "APP_", env_allowlist = ["HOST"])]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    host: Option<String>,
+
+    #[clap(long)]
+    port: Option<u16>,
+}
+
+#[test]
+fn only_listed_env_vars_are_read() {
+    let env = common::env(&[("APP_HOST", "env.example.com"), ("APP_PORT", "9000")]);
+    let opts = Opts::load_config_from(&common::args(&["app"]), &env, common::reader("port: 80\n")).unwrap();
+    assert_eq!(opts.host.as_deref(), Some("env.example.com"));
+    assert_eq!(opts.port, Some(80));
+}
@@ -0,0 +1,54 @@
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    #[serde(rename = "firstName")]
+    first_name: Option<String>,
+
+    #[clap(long)]
+    #[load_config(env = "DATABASE_URL")]
+    db: Option<String>,
+
+    #[clap(long)]
+    #[serde(rename = "region")]
+    #[load_config(env = "AWS_REGION")]
+    zone: Option<String>,
+}
+
+fn load(env: &[(&str, &str)], file: &str) -> Opts {
+    Opts::load_config_from(&common::args(&["app"]), &common::env(env), common::reader(file))
+    .unwrap()
+}
+
+#[test]
+fn serde_rename_only_names_both_the_env_var_and_the_file_key() {
+    assert_eq!(load(&[("FIRSTNAME", "Env")], "").first_name.as_deref(), Some("Env"));
+    assert_eq!(load(&[("FIRST_NAME", "Env")], "").first_name, None);
+    assert_eq!(load(&[], "firstName: Bob\n").first_name.as_deref(), Some("Bob"));
+    let description = Opts::describe();
+    let field = description.fields.iter().find(|field| field.name == "first_name").unwrap();
+    assert_eq!(field.file_key, "firstName");
+    assert_eq!(field.env.as_deref(), Some("FIRSTNAME"));
+}
+
+#[test]
+fn load_config_env_only_reads_the_named_var() {
+    assert_eq!(load(&[("DATABASE_URL", "postgres://")], "").db.as_deref(), Some("postgres://"));
+    assert_eq!(load(&[("DB", "postgres://")], "").db, None);
+}
+
+#[test]
+fn load_config_env_wins_over_serde_rename_for_the_env_var() {
+    assert_eq!(load(&[("AWS_REGION", "eu")], "").zone.as_deref(), Some("eu"));
+    assert_eq!(load(&[("REGION", "eu")], "").zone, None);
+    assert_eq!(load(&[], "region: us\n").zone.as_deref(), Some("us"));
+}
@@ -0,0 +1,76 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(field_refs)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, default_value = "1")]
+    level: u16,
+
+    #[clap(long, default_value = "${b}")]
+    a: String,
+
+    #[clap(long, default_value = "${a}")]
+    b: String,
+}
+
+fn load(cli: &[&str], file: &'static str) -> Result<Opts, ConfigError> {
+    Opts::load_config_from(&common::args(cli), &common::env(&[]), common::reader(file))
+}
+
+#[test]
+fn bad_arguments_are_returned_instead_of_exiting() {
+    let err = load(&["app", "--nope"], "").unwrap_err();
+    assert!(matches!(err, ConfigError::Args(_)), "{err:?}");
+}
+
+#[test]
+fn duplicate_keys_name_the_key_and_file() {
+    let err = load(&["app", "--a", "x", "--b", "y"], "level: 1\nlevel: 2\n").unwrap_err();
+    assert!(matches!(&err, ConfigError::DuplicateKey { key, path } if key == "level" && path == "/config.yml"), "{err:?}");
+}
+
+#[test]
+fn reference_cycles_list_the_cycle() {
+    let err = load(&["app"], "").unwrap_err();
+    assert!(matches!(&err, ConfigError::CyclicReference(cycle) if cycle == &["a", "b", "a"]), "{err:?}");
+}
+
+#[test]
+fn unreadable_contents_are_an_error_not_a_missing_file() {
+    let path = std::env::temp_dir().join(format!("load-config-errors-{}.yml", std::process::id()));
+    std::fs::write(&path, b"level: \xff\n").unwrap();
+    let result = Opts::load_config_from_args(&common::args(&["app", "--a", "x", "--b", "y", "--config", path.to_str().unwrap()]));
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(ConfigError::Parse { .. })), "{result:?}");
+}
+
+mod size_limit {
+    use clap::Parser;
+    use config_loader_trait::{ConfigError, ConfigLoader};
+    use load_config_derive::LoadConfig;
+    use serde::Deserialize;
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    #[load_config(max_file_size = "16")]
+    struct Opts {
+        #[clap(short, long, default_value = "/config.yml")]
+        config: String,
+
+        #[clap(long, default_value = "")]
+        name: String,
+    }
+
+    #[test]
+    fn oversized_files_are_too_large() {
+        let result = Opts::load_config_from(&super::common::args(&["app"]), &super::common::env(&[]), |_| Ok(format!("name: {}\n", "x".repeat(32))));
+        assert!(matches!(&result, Err(ConfigError::TooLarge { limit: 16, .. })), "{result:?}");
+    }
+}
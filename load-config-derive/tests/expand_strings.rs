@@ -0,0 +1,52 @@
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(expand_strings)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    url: Option<String>,
+
+    #[clap(long)]
+    hosts: Vec<String>,
+}
+
+#[test]
+fn env_references_in_strings_are_expanded() {
+    let env = common::env(&[("DB_HOST", "db.internal"), ("REGION", "eu")]);
+    let file = "url: postgres://${DB_HOST}:5432/${MISSING}\nhosts: [\"${REGION}-1\", \"${REGION}-2\"]\n";
+    let opts = Opts::load_config_from(&common::args(&["app"]), &env, common::reader(file)).unwrap();
+    assert_eq!(opts.url.as_deref(), Some("postgres://db.internal:5432/${MISSING}"));
+    assert_eq!(opts.hosts, ["eu-1", "eu-2"]);
+}
+
+mod strict {
+    use clap::Parser;
+    use config_loader_trait::{ConfigError, ConfigLoader};
+    use load_config_derive::LoadConfig;
+    use serde::Deserialize;
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    #[load_config(expand_strings = "error")]
+    struct Opts {
+        #[clap(short, long, default_value = "/config.yml")]
+        config: String,
+
+        #[clap(long)]
+        url: Option<String>,
+    }
+
+    #[test]
+    fn an_undefined_variable_is_an_error() {
+        let file = "url: postgres://${DB_HOST}/app\n";
+        let err = Opts::load_config_from(&super::common::args(&["app"]), &super::common::env(&[]), super::common::reader(file)).unwrap_err();
+        assert!(matches!(&err, ConfigError::Invalid(message) if message.contains("`DB_HOST`")), "{err:?}");
+    }
+}
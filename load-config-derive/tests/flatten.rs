@@ -0,0 +1,175 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader, Source};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+// Both derives share this module: the types they use come from
+// config_loader_trait, so nothing they generate collides.
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Database {
+    /// Database host.
+    #[clap(long = "db-host", id = "db_host")]
+    #[load_config(required)]
+    host: String,
+
+    #[clap(long = "db-port", default_value = "5432")]
+    port: u16,
+
+    #[clap(long)]
+    #[load_config(env = "DB_POOL_MAX")]
+    pool_max: Option<u32>,
+}
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    host: Option<String>,
+
+    #[clap(flatten)]
+    database: Database,
+}
+
+fn load(cli: &[&str], env: &[(&str, &str)], file: &str) -> Result<Opts, ConfigError> {
+    Opts::load_config_from(&common::args(cli), &common::env(env), common::reader(file))
+}
+
+#[test]
+fn nested_fields_load_from_their_section() {
+    let opts = load(&["app"], &[], "database:\n  host: db.local\n").unwrap();
+    assert_eq!(opts.database.host, "db.local");
+    assert_eq!(opts.database.port, 5432);
+}
+
+#[test]
+fn describe_includes_nested_fields() {
+    let description = Opts::describe();
+    let names = description.fields.iter().map(|field| field.file_key.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, ["config", "host", "database.host", "database.port", "database.pool-max"]);
+    let host = description.fields.iter().find(|field| field.file_key == "database.host").unwrap();
+    assert_eq!(host.name, "host");
+    assert_eq!(host.doc, Some("Database host."));
+    assert_eq!(host.env.as_deref(), Some("DATABASE__HOST"));
+    assert!(host.required);
+    let port = description.fields.iter().find(|field| field.file_key == "database.port").unwrap();
+    assert_eq!(port.default.as_deref(), Some("5432"));
+    let pool_max = description.fields.iter().find(|field| field.file_key == "database.pool-max").unwrap();
+    assert_eq!(pool_max.env.as_deref(), Some("DB_POOL_MAX"));
+    assert!(!pool_max.required);
+}
+
+#[test]
+fn nested_env_vars_are_prefixed_with_the_section() {
+    let opts = load(&["app"], &[("HOST", "top"), ("DATABASE__HOST", "db.env"), ("DATABASE__PORT", "6543")], "").unwrap();
+    assert_eq!(opts.host.as_deref(), Some("top"));
+    assert_eq!(opts.database.host, "db.env");
+    assert_eq!(opts.database.port, 6543);
+}
+
+#[test]
+fn a_nested_env_alias_bypasses_the_section_name() {
+    let opts = load(&["app"], &[("DB_POOL_MAX", "8"), ("DATABASE__POOL_MAX", "99")], "database:\n  host: db\n").unwrap();
+    assert_eq!(opts.database.pool_max, Some(8));
+}
+
+#[test]
+fn nested_errors_keep_the_nested_error() {
+    let err = load(&["app"], &[], "").unwrap_err();
+    let ConfigError::Nested { field, source } = &err else {
+        panic!("expected a nested error, got {err:?}");
+    };
+    assert_eq!(*field, "database");
+    assert!(matches!(**source, ConfigError::MissingRequired("host")), "{source:?}");
+    assert_eq!(err.to_string(), "database: missing required config value `host`");
+}
+
+mod optional {
+    use clap::Parser;
+    use config_loader_trait::ConfigLoader;
+    use load_config_derive::LoadConfig;
+    use serde::Deserialize;
+
+    mod tls {
+        use clap::Parser;
+        use load_config_derive::LoadConfig;
+        use serde::Deserialize;
+
+        #[derive(Parser, Deserialize, Debug, LoadConfig)]
+        pub struct Tls {
+            #[clap(long)]
+            pub cert: Option<String>,
+
+            #[clap(long = "tls-port", default_value = "443")]
+            pub port: u16,
+        }
+    }
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    struct Opts {
+        #[clap(short, long, default_value = "/config.yml")]
+        config: String,
+
+        #[clap(flatten)]
+        tls: Option<tls::Tls>,
+    }
+
+    fn load(cli: &[&str], env: &[(&str, &str)], file: &str) -> Opts {
+        Opts::load_config_from(&super::common::args(cli), &super::common::env(env), super::common::reader(file))
+        .unwrap()
+    }
+
+    #[test]
+    fn defaults_alone_leave_the_block_absent() {
+        assert!(load(&["app"], &[], "").tls.is_none());
+        assert!(Opts::default_values().unwrap().tls.is_none());
+    }
+
+    #[test]
+    fn an_empty_section_leaves_the_block_absent() {
+        assert!(load(&["app"], &[], "tls: {}\n").tls.is_none());
+    }
+
+    #[test]
+    fn any_layer_setting_a_field_makes_the_block_present() {
+        let tls = load(&["app"], &[], "tls:\n  cert: file.pem\n").tls.unwrap();
+        assert_eq!(tls.cert.as_deref(), Some("file.pem"));
+        assert_eq!(tls.port, 443);
+        let tls = load(&["app"], &[("TLS__CERT", "env.pem")], "").tls.unwrap();
+        assert_eq!(tls.cert.as_deref(), Some("env.pem"));
+        let tls = load(&["app", "--cert", "cli.pem"], &[], "").tls.unwrap();
+        assert_eq!(tls.cert.as_deref(), Some("cli.pem"));
+    }
+
+    #[test]
+    fn passing_a_default_value_on_the_command_line_makes_the_block_present() {
+        let tls = load(&["app", "--tls-port", "443"], &[], "").tls.unwrap();
+        assert_eq!(tls.cert, None);
+        assert_eq!(tls.port, 443);
+    }
+
+    #[test]
+    fn update_from_args_fills_an_absent_block() {
+        let mut opts = load(&["app"], &[], "");
+        opts.update_from_args(&["app".to_string()]).unwrap();
+        assert!(opts.tls.is_none());
+        opts.update_from_args(&["app".to_string(), "--cert".to_string(), "new.pem".to_string()]).unwrap();
+        let tls = opts.tls.unwrap();
+        assert_eq!(tls.cert.as_deref(), Some("new.pem"));
+        assert_eq!(tls.port, 443);
+    }
+}
+
+#[test]
+fn nested_value_sources_use_dotted_names() {
+    let ctx = common::context(&["app", "--db-port", "6000"], &[("DB_POOL_MAX", "4")], "host: top\ndatabase:\n  host: db\n");
+    let (_, sources) = OptsConfigLoaderOpts::load_with_sources(&ctx, "", None).unwrap();
+    assert_eq!(sources["host"], Source::File);
+    assert_eq!(sources["database.host"], Source::File);
+    assert_eq!(sources["database.port"], Source::Cli);
+    assert_eq!(sources["database.pool_max"], Source::Env);
+    assert!(!sources.contains_key("database"));
+}
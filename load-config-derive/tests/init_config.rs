@@ -0,0 +1,48 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    /// Where the server listens.
+    #[clap(long, default_value = "localhost")]
+    host: String,
+
+    #[clap(long, default_value = "8080")]
+    port: u16,
+}
+
+fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("load-config-init-{name}-{}.yml", std::process::id()))
+}
+
+#[test]
+fn the_written_file_loads_back_as_the_defaults() {
+    let path = temp_path("defaults");
+    Opts::init_config(&path, false).unwrap();
+    let loaded = Opts::config_values(&path);
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let loaded = loaded.unwrap();
+    assert_eq!((loaded.host.as_str(), loaded.port), ("localhost", 8080));
+    assert!(contents.contains("host: localhost"), "{contents}");
+}
+
+#[test]
+fn an_existing_file_is_only_replaced_with_overwrite() {
+    let path = temp_path("existing");
+    std::fs::write(&path, "port: 1\n").unwrap();
+    let refused = Opts::init_config(&path, false);
+    let kept = std::fs::read_to_string(&path).unwrap();
+    let replaced = Opts::init_config(&path, true);
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(&refused, Err(ConfigError::Io(err)) if err.kind() == std::io::ErrorKind::AlreadyExists), "{refused:?}");
+    assert_eq!(kept, "port: 1\n");
+    replaced.unwrap();
+    assert!(contents.contains("port: 8080"), "{contents}");
+}
@@ -0,0 +1,46 @@
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    tags: Vec<String>,
+
+    #[clap(long)]
+    #[load_config(merge = "append")]
+    plugins: Vec<String>,
+}
+
+fn load(cli: &[&str], env: &[(&str, &str)], file: &str) -> Opts {
+    Opts::load_config_from(&common::args(cli), &common::env(env), common::reader(file))
+    .unwrap()
+}
+
+#[test]
+fn an_empty_env_value_is_an_empty_list() {
+    assert_eq!(load(&["app"], &[("TAGS", "")], "tags: [a, b]\n").tags, Vec::<String>::new());
+}
+
+#[test]
+fn a_single_env_value_is_a_one_element_list() {
+    assert_eq!(load(&["app"], &[("TAGS", "solo")], "").tags, ["solo"]);
+    assert_eq!(load(&["app"], &[("TAGS", " a , b ")], "").tags, ["a", "b"]);
+}
+
+#[test]
+fn lists_replace_by_default() {
+    assert_eq!(load(&["app"], &[("TAGS", "env")], "tags: [file]\n").tags, ["env"]);
+}
+
+#[test]
+fn append_lists_concatenate_across_layers() {
+    let opts = load(&["app", "--plugins", "cli"], &[("PLUGINS", "env")], "plugins: [file]\n");
+    assert_eq!(opts.plugins, ["file", "env", "cli"]);
+}
@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, default_value = "default")]
+    cli: String,
+
+    #[clap(long, default_value = "default")]
+    env: String,
+
+    #[clap(long, default_value = "default")]
+    file: String,
+
+    #[clap(long, default_value = "default")]
+    unset: String,
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn each_layer_beats_the_ones_below_it() {
+    let files = HashMap::from([("/config.yml", "cli: file\nenv: file\nfile: file\n".to_string())]);
+    let env = HashMap::from([("CLI".to_string(), "env".to_string()), ("ENV".to_string(), "env".to_string())]);
+    // The reader borrows `files`; it does not have to be 'static.
+    let opts = Opts::load_config_from(&args(&["app", "--cli", "cli"]), &env, |path| {
+        files.get(path).cloned().ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    })
+    .unwrap();
+    assert_eq!(opts.cli, "cli");
+    assert_eq!(opts.env, "env");
+    assert_eq!(opts.file, "file");
+    assert_eq!(opts.unset, "default");
+}
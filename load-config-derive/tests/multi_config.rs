@@ -0,0 +1,177 @@
+use clap::Parser;
+use config_loader_trait::{ConfigLoader, LoadContext};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_values = ["/base.yml", "/local.yml"])]
+    config: Vec<String>,
+
+    #[clap(long)]
+    host: Option<String>,
+
+    #[clap(long)]
+    port: Option<u16>,
+}
+
+fn read(path: &str) -> std::io::Result<String> {
+    match path {
+        "/base.yml" => Ok("host: base.example.com\nport: 80\nlogging:\n  level: info\n  format: json\n".to_string()),
+        "/local.yml" => Ok("host: localhost\nlogging:\n  level: debug\n".to_string()),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    }
+}
+
+fn context(args: &[&str]) -> LoadContext<'static> {
+    LoadContext {
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        env: Default::default(),
+        read_file: Some(std::rc::Rc::new(|path: &std::path::Path| read(&path.to_string_lossy()))),
+        ..LoadContext::from_process()
+    }
+}
+
+#[test]
+fn local_overrides_base() {
+    let opts = Opts::load_config_with_context(&context(&["app"])).unwrap();
+    assert_eq!(opts.host.as_deref(), Some("localhost"));
+    assert_eq!(opts.port, Some(80));
+}
+
+#[test]
+fn raw_config_value_merges_every_file() {
+    let raw = OptsConfigLoaderOpts::raw_config_value(&context(&["app"])).unwrap().unwrap();
+    let expected: serde_yaml::Value =
+        serde_yaml::from_str("host: localhost\nport: 80\nlogging:\n  level: debug\n  format: json\n").unwrap();
+    assert_eq!(raw, expected);
+}
+
+#[test]
+fn missing_files_are_skipped() {
+    let raw = OptsConfigLoaderOpts::raw_config_value(&context(&["app", "--config", "/base.yml", "--config", "/missing.yml"]))
+        .unwrap()
+        .unwrap();
+    assert_eq!(raw["host"], "base.example.com");
+}
+
+mod first_wins {
+    use super::read;
+    use clap::Parser;
+    use config_loader_trait::{ConfigLoader, LoadContext};
+    use load_config_derive::LoadConfig;
+    use serde::Deserialize;
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    #[load_config(multi_config_order = "first_wins")]
+    struct Opts {
+        #[clap(short, long, default_values = ["/local.yml", "/base.yml"])]
+        config: Vec<String>,
+
+        #[clap(long)]
+        host: Option<String>,
+
+        #[clap(long)]
+        port: Option<u16>,
+    }
+
+    fn context() -> LoadContext<'static> {
+        LoadContext {
+            args: vec!["app".to_string()],
+            env: Default::default(),
+            read_file: Some(std::rc::Rc::new(|path: &std::path::Path| read(&path.to_string_lossy()))),
+            ..LoadContext::from_process()
+        }
+    }
+
+    #[test]
+    fn earlier_files_override_later_ones() {
+        let opts = Opts::load_config_with_context(&context()).unwrap();
+        assert_eq!(opts.host.as_deref(), Some("localhost"));
+        assert_eq!(opts.port, Some(80));
+    }
+
+    #[test]
+    fn raw_config_value_follows_the_order() {
+        let raw = OptsConfigLoaderOpts::raw_config_value(&context()).unwrap().unwrap();
+        assert_eq!(raw["host"], "localhost");
+        assert_eq!(raw["logging"]["level"], "debug");
+        assert_eq!(raw["logging"]["format"], "json");
+    }
+}
+
+mod clearing {
+    use clap::Parser;
+    use config_loader_trait::{ConfigLoader, LoadContext};
+    use load_config_derive::LoadConfig;
+    use serde::Deserialize;
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    struct Tls {
+        #[clap(long)]
+        cert: Option<String>,
+
+        #[clap(long)]
+        key: Option<String>,
+    }
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    struct Opts {
+        #[clap(short, long, default_values = ["/base.yml", "/local.yml"])]
+        config: Vec<String>,
+
+        #[clap(long)]
+        host: Option<String>,
+
+        #[clap(long, default_value = "30")]
+        timeout: u64,
+
+        #[clap(long)]
+        #[load_config(merge = "append")]
+        tags: Vec<String>,
+
+        #[clap(flatten)]
+        tls: Tls,
+    }
+
+    fn load(local: &'static str) -> Opts {
+        let ctx = LoadContext {
+            args: vec!["app".to_string()],
+            env: Default::default(),
+            read_file: Some(std::rc::Rc::new(move |path: &std::path::Path| match path.to_str() {
+                Some("/base.yml") => Ok("host: base\ntimeout: 5\ntags: [a]\ntls:\n  cert: base.pem\n  key: base.key\n".to_string()),
+                Some("/local.yml") => Ok(local.to_string()),
+                _ => Err(std::io::ErrorKind::NotFound.into()),
+            })),
+            ..LoadContext::from_process()
+        };
+        Opts::load_config_with_context(&ctx).unwrap()
+    }
+
+    #[test]
+    fn a_missing_key_inherits_the_base_value() {
+        let opts = load("host: local\n");
+        assert_eq!(opts.host.as_deref(), Some("local"));
+        assert_eq!(opts.timeout, 5);
+        assert_eq!(opts.tags, ["a"]);
+        assert_eq!(opts.tls.cert.as_deref(), Some("base.pem"));
+    }
+
+    #[test]
+    fn a_null_resets_the_field_to_its_default() {
+        let opts = load("host: ~\ntimeout: ~\ntags: ~\n");
+        assert_eq!(opts.host, None);
+        assert_eq!(opts.timeout, 30);
+        assert!(opts.tags.is_empty());
+        assert_eq!(opts.tls.cert.as_deref(), Some("base.pem"));
+    }
+
+    #[test]
+    fn nulls_reach_into_nested_sections() {
+        let opts = load("tls:\n  cert: ~\n");
+        assert_eq!(opts.tls.cert, None);
+        assert_eq!(opts.tls.key.as_deref(), Some("base.key"));
+        let opts = load("tls: ~\n");
+        assert_eq!((opts.tls.cert, opts.tls.key), (None, None));
+    }
+}
@@ -0,0 +1,77 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use clap::Parser;
+use config_loader_trait::{ConfigError, LoadContext, SsmParameterStore};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    #[load_config(from_param = "/app/db/password")]
+    password: Option<String>,
+
+    #[clap(long)]
+    #[load_config(from_param = "/app/db/user")]
+    user: Option<String>,
+
+    #[clap(long)]
+    #[load_config(from_param = "/app/db/port")]
+    port: Option<u16>,
+}
+
+// Stands in for the aws CLI: prints the value of the parameter named after
+// `--name`, or fails the way the CLI does for a missing one.
+const FAKE_AWS: &str = r#"#!/bin/sh
+echo "$@" >> "$0.log"
+case "$4" in
+    /app/db/password) echo "s3cret" ;;
+    /app/db/port) echo "6432" ;;
+    /app/broken) echo "An error occurred (AccessDeniedException)" >&2; exit 255 ;;
+    *) echo "An error occurred (ParameterNotFound) when calling the GetParameter operation: Parameter $4 not found." >&2; exit 254 ;;
+esac
+"#;
+
+fn fake_aws(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("load-config-fake-aws-{name}-{}", std::process::id()));
+    std::fs::write(&path, FAKE_AWS).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn load(store: &SsmParameterStore, env: &[(&str, &str)], file: &'static str) -> Opts {
+    let ctx = LoadContext {
+        args: vec!["app".to_string()],
+        env: env.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+        read_file: Some(std::rc::Rc::new(move |_: &std::path::Path| Ok(file.to_string()))),
+        ..LoadContext::from_process()
+    };
+    OptsConfigLoaderOpts::load(&ctx, "", Some(store)).unwrap().into_config().unwrap()
+}
+
+#[test]
+fn parameters_layer_above_the_file_and_below_env() {
+    let aws = fake_aws("layers");
+    let store = SsmParameterStore::new().program(&aws).region("eu-west-1");
+    let opts = load(&store, &[("PORT", "7000")], "password: file\nuser: file\nport: 5432\n");
+    assert_eq!(opts.password.as_deref(), Some("s3cret"));
+    assert_eq!(opts.port, Some(7000));
+    // A parameter the store does not have leaves the file's value.
+    assert_eq!(opts.user.as_deref(), Some("file"));
+    let log = std::fs::read_to_string(format!("{}.log", aws.display())).unwrap();
+    assert!(log.contains("ssm get-parameter --name /app/db/password --with-decryption --query Parameter.Value --output text --region eu-west-1"), "{log}");
+}
+
+#[test]
+fn a_failing_lookup_is_a_source_error() {
+    let aws = fake_aws("failing");
+    let store = SsmParameterStore::new().program(&aws);
+    let err = OptsConfigLoaderOpts::fetch_param(&store, "/app/broken").unwrap_err();
+    assert!(matches!(&err, ConfigError::Source(message) if message.contains("AccessDeniedException")), "{err:?}");
+}
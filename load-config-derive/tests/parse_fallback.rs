@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use clap::Parser;
+use config_loader_trait::{ConfigError, Layer, Source};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, default_value = "8")]
+    #[load_config(warn_on_parse_error)]
+    workers: u32,
+
+    #[clap(long, default_value = "30")]
+    timeout: u32,
+}
+
+fn load(env: &[(&str, &str)], file: &str) -> Result<(Opts, HashMap<String, Source>), ConfigError> {
+    let ctx = common::context(&["app"], env, file);
+    let (opts, sources) = OptsConfigLoaderOpts::load_with_sources(&ctx, "", None)?;
+    Ok((opts.into_config()?, sources))
+}
+
+#[test]
+fn a_bad_env_value_falls_back_to_the_file() {
+    let (opts, sources) = load(&[("WORKERS", "many")], "workers: 4\n").unwrap();
+    assert_eq!(opts.workers, 4);
+    assert_eq!(sources["workers"], Source::Fallback { rejected: Layer::Env, used: Layer::File });
+}
+
+#[test]
+fn a_bad_file_value_falls_back_to_the_default() {
+    let (opts, sources) = load(&[], "workers: many\ntimeout: 5\n").unwrap();
+    assert_eq!(opts.workers, 8);
+    assert_eq!(opts.timeout, 5);
+    assert_eq!(sources["workers"], Source::Fallback { rejected: Layer::File, used: Layer::Default });
+}
+
+#[test]
+fn a_rejected_value_below_the_winner_is_not_a_fallback() {
+    let (opts, sources) = load(&[("WORKERS", "2")], "workers: many\n").unwrap();
+    assert_eq!(opts.workers, 2);
+    assert_eq!(sources["workers"], Source::Env);
+}
+
+#[test]
+fn other_fields_still_fail_on_a_bad_file_value() {
+    assert!(matches!(load(&[], "timeout: soon\n"), Err(ConfigError::Parse { .. })));
+}
@@ -0,0 +1,50 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(profile_field = "profile")]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    profile: Option<String>,
+
+    #[clap(long)]
+    host: Option<String>,
+}
+
+fn list_profiles(name: &str, contents: &str) -> Result<Vec<String>, ConfigError> {
+    let path = std::env::temp_dir().join(format!("load-config-profiles-{name}-{}.yml", std::process::id()));
+    std::fs::write(&path, contents).unwrap();
+    let profiles = Opts::list_profiles(&path);
+    std::fs::remove_file(&path).unwrap();
+    profiles
+}
+
+#[test]
+fn profiles_are_the_top_level_sections() {
+    let profiles = list_profiles("sections", "dev:\n  host: localhost\nprod:\n  host: example.com\n").unwrap();
+    assert_eq!(profiles, ["dev", "prod"]);
+}
+
+#[test]
+fn a_file_without_profiles_lists_none() {
+    assert_eq!(list_profiles("empty", "").unwrap(), Vec::<String>::new());
+    assert_eq!(list_profiles("scalar", "just a string\n").unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn plain_settings_are_not_profiles() {
+    assert_eq!(list_profiles("flat", "first-name: John\nage: 42\n").unwrap(), Vec::<String>::new());
+    let profiles = list_profiles("mixed", "host: shared\ndev:\n  host: localhost\n").unwrap();
+    assert_eq!(profiles, ["dev"]);
+}
+
+#[test]
+fn a_missing_file_is_an_error() {
+    let result = Opts::list_profiles(std::path::Path::new("/load-config-profiles/missing.yml"));
+    assert!(matches!(result, Err(ConfigError::Io(_))), "{result:?}");
+}
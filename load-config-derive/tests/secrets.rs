@@ -0,0 +1,87 @@
+use clap::Parser;
+use config_loader_trait::{ConfigFormat, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+mod db {
+    use clap::Parser;
+    use load_config_derive::LoadConfig;
+    use serde::Deserialize;
+
+    #[derive(Parser, Deserialize, Debug, LoadConfig)]
+    pub struct Database {
+        #[clap(long)]
+        pub user: Option<String>,
+
+        #[clap(long)]
+        #[load_config(secret)]
+        pub password: Option<String>,
+    }
+}
+
+#[derive(Parser, Deserialize, LoadConfig)]
+#[load_config(debug)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    name: Option<String>,
+
+    #[clap(long)]
+    #[load_config(secret)]
+    api_key: Option<String>,
+
+    #[clap(flatten)]
+    database: db::Database,
+}
+
+fn load(file: &str) -> Opts {
+    Opts::load_config_from(&common::args(&["app"]), &common::env(&[]), common::reader(file))
+    .unwrap()
+}
+
+const FILE: &str = "name: app\napi-key: hunter2\ndatabase:\n  user: admin\n  password: swordfish\n";
+
+#[test]
+fn debug_redacts_secrets() {
+    let debug = format!("{:?}", load(FILE));
+    assert!(debug.contains("name: Some(\"app\")"), "{debug}");
+    assert!(debug.contains("api_key: \"***\""), "{debug}");
+    assert!(!debug.contains("hunter2"), "{debug}");
+    let opts_debug = format!("{:?}", OptsConfigLoaderOpts::from(&load(FILE)));
+    assert!(!opts_debug.contains("hunter2") && !opts_debug.contains("swordfish"), "{opts_debug}");
+}
+
+#[test]
+fn renderings_leave_secrets_out() {
+    let opts = load(FILE);
+    for rendered in [
+        opts.dump_minimal_config().unwrap(),
+        opts.dump_config().unwrap(),
+        opts.dump_config_as(ConfigFormat::Json).unwrap(),
+        opts.to_canonical_yaml().unwrap(),
+    ] {
+        assert!(rendered.contains("admin"), "{rendered}");
+        assert!(!rendered.contains("hunter2") && !rendered.contains("swordfish"), "{rendered}");
+    }
+}
+
+#[test]
+fn secrets_still_change_the_hash() {
+    let hash = load(FILE).config_hash();
+    assert_ne!(hash, load(&FILE.replace("hunter2", "hunter3")).config_hash());
+    assert_ne!(hash, load(&FILE.replace("swordfish", "marlin")).config_hash());
+}
+
+#[test]
+fn describe_flags_secrets() {
+    let description = Opts::describe();
+    let secret = |key: &str| description.fields.iter().find(|field| field.file_key == key).unwrap().secret;
+    assert!(secret("api-key"));
+    assert!(!secret("name"));
+    assert!(secret("database.password"));
+    assert!(!secret("database.user"));
+}
@@ -0,0 +1,27 @@
+use clap::Parser;
+use config_loader_trait::ConfigLoader;
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, default_value = "app")]
+    name: String,
+
+    #[clap(skip)]
+    #[load_config(skip)]
+    cache_path: String,
+}
+
+#[test]
+fn a_skipped_field_never_reads_its_env_var_or_file_key() {
+    let env = common::env(&[("CACHE_PATH", "/tmp/cache"), ("NAME", "env")]);
+    let opts = Opts::load_config_from(&common::args(&["app"]), &env, common::reader("cache-path: /file/cache\n")).unwrap();
+    assert_eq!(opts.name, "env");
+    assert_eq!(opts.cache_path, "");
+}
@@ -0,0 +1,37 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(template = "minijinja")]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    host: Option<String>,
+
+    #[clap(long, default_value = "1")]
+    workers: u32,
+}
+
+fn load(env: &[(&str, &str)], file: &str) -> Result<Opts, ConfigError> {
+    Opts::load_config_with_context(&common::context(&["app"], env, file))
+}
+
+#[test]
+fn the_file_is_rendered_before_it_is_parsed() {
+    let file = "host: {{ env.REGION }}.example.com\n{% if env.REGION == \"eu\" %}workers: 4{% endif %}\n";
+    let opts = load(&[("REGION", "eu")], file).unwrap();
+    assert_eq!(opts.host.as_deref(), Some("eu.example.com"));
+    assert_eq!(opts.workers, 4);
+}
+
+#[test]
+fn a_template_error_is_invalid() {
+    let err = load(&[], "host: {{ env.REGION\n").unwrap_err();
+    assert!(matches!(&err, ConfigError::Invalid(message) if message.starts_with("template error")), "{err:?}");
+}
@@ -0,0 +1,41 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+
+mod common;
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(validate = "check_ports")]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long, default_value = "8080")]
+    port: u16,
+
+    #[clap(long, default_value = "9090")]
+    admin_port: u16,
+}
+
+fn check_ports(opts: &Opts) -> Result<(), String> {
+    if opts.port == opts.admin_port {
+        return Err(format!("port and admin-port are both {}", opts.port));
+    }
+    Ok(())
+}
+
+fn load(cli: &[&str], file: &str) -> Result<Opts, ConfigError> {
+    Opts::load_config_from(&common::args(cli), &common::env(&[]), common::reader(file))
+}
+
+#[test]
+fn a_valid_config_loads() {
+    assert_eq!(load(&["app"], "port: 80\n").unwrap().port, 80);
+}
+
+#[test]
+fn a_rejected_config_is_a_validation_error() {
+    let err = load(&["app", "--admin-port", "80"], "port: 80\n").unwrap_err();
+    assert!(matches!(&err, ConfigError::Validation(message) if message == "port and admin-port are both 80"), "{err:?}");
+}
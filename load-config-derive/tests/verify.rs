@@ -0,0 +1,53 @@
+use clap::Parser;
+use config_loader_trait::{ConfigError, ConfigLoader, LoadContext};
+use load_config_derive::LoadConfig;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+#[derive(Parser, Deserialize, Debug, LoadConfig)]
+#[load_config(verify = "sha256")]
+struct Opts {
+    #[clap(short, long, default_value = "/config.yml")]
+    config: String,
+
+    #[clap(long)]
+    host: Option<String>,
+}
+
+const CONFIG: &str = "host: example.com\n";
+
+fn sha256(contents: &str) -> String {
+    Sha256::digest(contents).iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn load(digest: Option<String>) -> Result<Opts, ConfigError> {
+    let ctx = LoadContext {
+        args: vec!["app".to_string()],
+        env: Default::default(),
+        read_file: Some(std::rc::Rc::new(move |path: &std::path::Path| match (path.to_str(), &digest) {
+            (Some("/config.yml"), _) => Ok(CONFIG.to_string()),
+            (Some("/config.yml.sha256"), Some(digest)) => Ok(format!("{digest}  config.yml\n")),
+            _ => Err(std::io::ErrorKind::NotFound.into()),
+        })),
+        ..LoadContext::from_process()
+    };
+    Opts::load_config_with_context(&ctx)
+}
+
+#[test]
+fn a_file_matching_its_digest_loads() {
+    let opts = load(Some(sha256(CONFIG))).unwrap();
+    assert_eq!(opts.host.as_deref(), Some("example.com"));
+}
+
+#[test]
+fn a_file_not_matching_its_digest_is_rejected() {
+    let err = load(Some(sha256("host: evil.example.com\n"))).unwrap_err();
+    assert!(matches!(&err, ConfigError::IntegrityCheck { path, message } if path == "/config.yml" && message.starts_with("expected sha256")), "{err:?}");
+}
+
+#[test]
+fn a_missing_digest_is_rejected() {
+    let err = load(None).unwrap_err();
+    assert!(matches!(&err, ConfigError::IntegrityCheck { message, .. } if message.contains("/config.yml.sha256")), "{err:?}");
+}
@@ -4,6 +4,7 @@
 )]
 
 use clap::Parser;
+use config_loader_trait::ConfigLoader;
 use load_config_derive::LoadConfig;
 use serde::{Deserialize, Serialize};
 
@@ -20,6 +21,10 @@ struct Opts {
 
     #[clap(short, long, default_value = "42")]
     age: u8,
+
+    #[cfg(feature = "telemetry")]
+    #[clap(long)]
+    telemetry_endpoint: Option<String>,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
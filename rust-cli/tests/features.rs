@@ -0,0 +1,24 @@
+use std::process::Command;
+
+fn help() -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cli")).arg("--help").output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn telemetry_endpoint_is_an_arg_only_with_the_feature() {
+    assert_eq!(help().contains("--telemetry-endpoint"), cfg!(feature = "telemetry"));
+}
+
+#[test]
+fn loads_with_the_feature_on_or_off() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-cli"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("TELEMETRY_ENDPOINT", "https://telemetry.example.com")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.contains("telemetry.example.com"), cfg!(feature = "telemetry"), "{stdout}");
+}